@@ -0,0 +1,233 @@
+use crate::{client::GoogleSheetsReadOnlyClient, error::Error};
+use chrono::{DateTime, Utc};
+use google_sheets4::api::Spreadsheet;
+use serde::{Deserialize, Serialize};
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::{
+    env,
+    env::temp_dir,
+    fs,
+    fs::OpenOptions,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+/// Spreadsheets fetched so far, kept on disk under `LIBGSQLITE_CACHE_DIR`, or `libgsqlite` under
+/// the temporary directory by default.
+pub struct SpreadsheetCache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub created: DateTime<Utc>,
+    /// Version of the spreadsheet file on Google Drive at the time of fetching, which increases
+    /// whenever the spreadsheet is changed.
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Fields of the spreadsheet which were fetched, as a field mask.
+    #[serde(default)]
+    pub fields: String,
+    pub spreadsheet: Spreadsheet,
+}
+
+impl Default for SpreadsheetCache {
+    fn default() -> Self {
+        match env::var("LIBGSQLITE_CACHE_DIR") {
+            Ok(dir) => SpreadsheetCache::new(dir),
+            Err(_) => SpreadsheetCache::new(temp_dir().join("libgsqlite")),
+        }
+    }
+}
+
+impl SpreadsheetCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        SpreadsheetCache { dir: dir.into() }
+    }
+
+    /// Returns the spreadsheet last stored for the range, if any.
+    pub fn load(
+        &self,
+        sheet_id: impl Into<String>,
+        sheet_name: impl Into<String>,
+        range: impl Into<String>,
+    ) -> Result<Option<CacheEntry>, Error> {
+        let path = self.get_path(sheet_id, sheet_name, range)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        self.check_dir()?;
+
+        let file = restrict(OpenOptions::new().read(true)).open(path)?;
+        Ok(Some(serde_json::from_reader(BufReader::new(file))?))
+    }
+
+    pub fn store(
+        &self,
+        sheet_id: impl Into<String>,
+        sheet_name: impl Into<String>,
+        range: impl Into<String>,
+        spreadsheet: &Spreadsheet,
+        version: Option<String>,
+        fields: impl Into<String>,
+    ) -> Result<(), Error> {
+        let path = self.get_path(sheet_id, sheet_name, range)?;
+        self.create_dir()?;
+
+        let file =
+            restrict(OpenOptions::new().write(true).create(true).truncate(true)).open(path)?;
+
+        serde_json::to_writer(
+            BufWriter::new(file),
+            &CacheEntry {
+                created: Utc::now(),
+                version,
+                fields: fields.into(),
+                spreadsheet: spreadsheet.clone(),
+            },
+        )?;
+        Ok(())
+    }
+
+    // The directory has a predictable name in the shared temporary directory by default, hence it's
+    // created only for the user, and refused if someone else has created it, as entries in it are
+    // served in offline mode as if they were fetched.
+    fn create_dir(&self) -> Result<(), Error> {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(target_family = "unix")]
+        builder.mode(0o700);
+        builder.create(&self.dir)?;
+
+        self.check_dir()
+    }
+
+    // Fails unless the directory is owned by the user, and not writable by others.
+    fn check_dir(&self) -> Result<(), Error> {
+        #[cfg(target_family = "unix")]
+        {
+            let metadata = fs::symlink_metadata(&self.dir)?;
+            // SAFETY: geteuid() has no preconditions, and never fails
+            let uid = unsafe { libc::geteuid() };
+            if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o022 != 0 {
+                return Err(Error::InsecureCacheDir(self.dir.display().to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    // Sheet name is hex-encoded as it may contain any character, and `:` in range is replaced as
+    // it's not allowed in file names on Windows.
+    fn get_path(
+        &self,
+        sheet_id: impl Into<String>,
+        sheet_name: impl Into<String>,
+        range: impl Into<String>,
+    ) -> Result<PathBuf, Error> {
+        let id = GoogleSheetsReadOnlyClient::parse_sheet_id(sheet_id)?;
+        let sheet_name = sheet_name
+            .into()
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        Ok(self.dir.join(format!(
+            "{}_{}_{}.json",
+            id,
+            sheet_name,
+            range.into().replace(':', "-")
+        )))
+    }
+}
+
+// Files in the cache are opened without following symlinks, which may point at any file of the
+// user, and created with permission only for the user.
+fn restrict(options: &mut OpenOptions) -> &mut OpenOptions {
+    #[cfg(target_family = "unix")]
+    options.custom_flags(libc::O_NOFOLLOW).mode(0o600);
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cache::SpreadsheetCache, error::Error};
+    use google_sheets4::api::Spreadsheet;
+    use std::env::temp_dir;
+    #[cfg(target_family = "unix")]
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    #[test]
+    fn test_store_and_load() {
+        let cache = SpreadsheetCache::new(temp_dir().join("libgsqlite-test-cache"));
+        let spreadsheet = Spreadsheet {
+            spreadsheet_id: Some("some_random_id".to_string()),
+            ..Default::default()
+        };
+
+        assert!(cache
+            .load("some_random_id", "シート 1", "A1:B2")
+            .unwrap()
+            .is_none());
+
+        cache
+            .store(
+                "https://docs.google.com/spreadsheets/d/some_random_id/edit",
+                "シート 1",
+                "A2:B3",
+                &spreadsheet,
+                Some("42".to_string()),
+                "spreadsheetId",
+            )
+            .unwrap();
+        let entry = cache
+            .load("some_random_id", "シート 1", "A2:B3")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Some("some_random_id".to_string()),
+            entry.spreadsheet.spreadsheet_id
+        );
+        assert_eq!(Some("42".to_string()), entry.version);
+        assert_eq!("spreadsheetId", entry.fields);
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_refuse_insecure_cache() {
+        let dir = temp_dir().join("libgsqlite-test-insecure-cache");
+        let _ = fs::remove_dir_all(&dir);
+        let cache = SpreadsheetCache::new(&dir);
+        let spreadsheet = Spreadsheet::default();
+        let store = || cache.store("some_random_id", "Sheet1", "A1:B2", &spreadsheet, None, "");
+
+        // created only for the user
+        store().unwrap();
+        assert_eq!(
+            0o700,
+            fs::metadata(&dir).unwrap().permissions().mode() & 0o777
+        );
+
+        // symlinks planted in the directory are not followed
+        let target = temp_dir().join("libgsqlite-test-insecure-cache-target");
+        fs::write(&target, "precious").unwrap();
+        let path = cache.get_path("some_random_id", "Sheet1", "A1:B2").unwrap();
+        fs::remove_file(&path).unwrap();
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+        assert!(store().is_err());
+        assert_eq!("precious", fs::read_to_string(&target).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        // a directory writable by others is refused, as entries may have been planted
+        store().unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(matches!(store(), Err(Error::InsecureCacheDir(_))));
+        assert!(matches!(
+            cache.load("some_random_id", "Sheet1", "A1:B2"),
+            Err(Error::InsecureCacheDir(_))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&target).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use serde_json::{json, Value};
+
+/// A change to be applied to a sheet by `batchUpdate`. Indices are zero-based, and computed against
+/// the sheet after preceding changes in the same batch are applied.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Sets a value to a cell. A string starting with `=` is written as a formula, and `null`
+    /// clears the cell.
+    UpdateCell {
+        row_index: usize,
+        column_index: usize,
+        value: Value,
+    },
+    /// Clears values of cells in `[start_column_index, end_column_index)` of a row.
+    ClearRow {
+        row_index: usize,
+        start_column_index: usize,
+        end_column_index: usize,
+    },
+    /// Deletes a row. Rows below it are shifted up.
+    DeleteRow { row_index: usize },
+}
+
+impl Change {
+    pub(crate) fn to_request(&self, grid_id: i32) -> Value {
+        match self {
+            Change::UpdateCell {
+                row_index,
+                column_index,
+                value,
+            } => json!({
+                "updateCells": {
+                    "start": {
+                        "sheetId": grid_id,
+                        "rowIndex": row_index,
+                        "columnIndex": column_index,
+                    },
+                    "rows": [{ "values": [to_cell_data(value)] }],
+                    "fields": "userEnteredValue",
+                }
+            }),
+            Change::ClearRow {
+                row_index,
+                start_column_index,
+                end_column_index,
+            } => json!({
+                "updateCells": {
+                    "range": {
+                        "sheetId": grid_id,
+                        "startRowIndex": row_index,
+                        "endRowIndex": row_index + 1,
+                        "startColumnIndex": start_column_index,
+                        "endColumnIndex": end_column_index,
+                    },
+                    "fields": "userEnteredValue",
+                }
+            }),
+            Change::DeleteRow { row_index } => json!({
+                "deleteDimension": {
+                    "range": {
+                        "sheetId": grid_id,
+                        "dimension": "ROWS",
+                        "startIndex": row_index,
+                        "endIndex": row_index + 1,
+                    }
+                }
+            }),
+        }
+    }
+}
+
+fn to_cell_data(value: &Value) -> Value {
+    match value {
+        Value::String(s) if s.starts_with('=') => {
+            json!({ "userEnteredValue": { "formulaValue": s } })
+        }
+        Value::String(s) => json!({ "userEnteredValue": { "stringValue": s } }),
+        Value::Number(n) => json!({ "userEnteredValue": { "numberValue": n } }),
+        Value::Bool(b) => json!({ "userEnteredValue": { "boolValue": b } }),
+        _ => json!({}),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::change::Change;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_request() {
+        assert_eq!(
+            json!({
+                "updateCells": {
+                    "start": { "sheetId": 7, "rowIndex": 3, "columnIndex": 1 },
+                    "rows": [{ "values": [{ "userEnteredValue": { "formulaValue": "=1+1" } }] }],
+                    "fields": "userEnteredValue",
+                }
+            }),
+            Change::UpdateCell {
+                row_index: 3,
+                column_index: 1,
+                value: json!("=1+1"),
+            }
+            .to_request(7)
+        );
+        assert_eq!(
+            json!({
+                "updateCells": {
+                    "start": { "sheetId": 7, "rowIndex": 3, "columnIndex": 1 },
+                    "rows": [{ "values": [{}] }],
+                    "fields": "userEnteredValue",
+                }
+            }),
+            Change::UpdateCell {
+                row_index: 3,
+                column_index: 1,
+                value: json!(null),
+            }
+            .to_request(7)
+        );
+        assert_eq!(
+            json!({
+                "deleteDimension": {
+                    "range": { "sheetId": 7, "dimension": "ROWS", "startIndex": 3, "endIndex": 4 }
+                }
+            }),
+            Change::DeleteRow { row_index: 3 }.to_request(7)
+        );
+    }
+}
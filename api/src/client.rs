@@ -1,30 +1,59 @@
-use crate::error::{
-    Error,
-    Error::{CodeMissing, InvalidRedirectUrl, InvalidSheetId, UnexpectedResponse, UnexpectedToken},
+use crate::{
+    cache::SpreadsheetCache,
+    change::Change,
+    error::{
+        Error,
+        Error::{
+            CodeMissing, InvalidRedirectUrl, InvalidSheetId, UnexpectedResponse, UnexpectedToken,
+        },
+    },
+    value_range::{DateTimeRenderOption, ValueRange, ValueRenderOption},
 };
 use chrono::{DateTime, Duration, Utc};
-use google_sheets4::api::Spreadsheet;
+use google_sheets4::api::{BatchUpdateSpreadsheetResponse, Spreadsheet};
 use oauth2::{
     basic::{BasicClient, BasicTokenResponse, BasicTokenType},
-    reqwest::http_client,
     url::Url,
     AccessToken, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
-    EmptyExtraTokenFields, RedirectUrl, Scope, TokenResponse, TokenUrl,
+    EmptyExtraTokenFields, HttpRequest, HttpResponse, RedirectUrl, RefreshToken, Scope,
+    TokenResponse, TokenUrl,
+};
+use reqwest::{
+    blocking::{Client as HttpClient, RequestBuilder, Response},
+    header::RETRY_AFTER,
+    redirect::Policy,
+    Certificate, Proxy, StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Deserializer;
+use serde_json::{json, Deserializer, Value};
 #[cfg(target_family = "unix")]
 use std::os::unix::fs::PermissionsExt;
 use std::{
+    collections::hash_map::RandomState,
     env::temp_dir,
+    fs,
     fs::File,
-    io::{BufRead, BufReader, Write},
+    hash::{BuildHasher, Hasher},
+    io::{BufRead, BufReader, Read, Write},
     net::TcpListener,
     ops::Sub,
     path::PathBuf,
+    sync::OnceLock,
+    thread, time,
+    time::Instant,
 };
 use typed_builder::TypedBuilder;
 
+/// Fields of a spreadsheet fetched by default, which are the ones needed to read cell values.
+pub const DEFAULT_FIELDS: &str = "spreadsheetId,sheets(properties(sheetId,title),data(startRow,startColumn,rowData.values(formattedValue,effectiveValue,effectiveFormat.numberFormat)))";
+
+/// Fields fetched along with metadata of cells, i.e. formulas, notes, hyperlinks, and formats.
+pub const METADATA_FIELDS: &str = "spreadsheetId,sheets(properties(sheetId,title),data(startRow,startColumn,rowData.values(formattedValue,effectiveValue,effectiveFormat,userEnteredValue.formulaValue,note,hyperlink,textFormatRuns)))";
+
+/// Fields fetched along with all properties of cells.
+pub const CELL_FIELDS: &str =
+    "spreadsheetId,sheets(properties(sheetId,title),data(startRow,startColumn,rowData.values))";
+
 #[derive(TypedBuilder)]
 pub struct GoogleSheetsReadOnlyClient {
     #[builder(setter(into))]
@@ -39,14 +68,59 @@ pub struct GoogleSheetsReadOnlyClient {
     google_token_url: String,
     #[builder(setter(into), default = "https://content-sheets.googleapis.com/v4/spreadsheets/".to_string())]
     content_url: String,
+    #[builder(setter(into), default = "https://www.googleapis.com/drive/v3/files/".to_string())]
+    drive_url: String,
+    // when set, spreadsheets are fetched only if they've been changed since cached
+    #[builder(default, setter(strip_option))]
+    cache: Option<SpreadsheetCache>,
     #[builder(default = false)]
     cache_access_token: bool,
+    #[builder(setter(into), default = temp_dir())]
+    access_token_cache_dir: PathBuf,
+    #[builder(default = true)]
+    read_only: bool,
+    // field mask of the spreadsheet to fetch, which trims the grid data; empty to fetch all fields
+    #[builder(setter(into), default = DEFAULT_FIELDS.to_string())]
+    fields: String,
+    // access token to use instead of going through the OAuth flow
+    #[builder(default, setter(strip_option, into))]
+    access_token: Option<String>,
+    // requests rate-limited, or failed on Google's side, are retried up to this number of attempts
+    #[builder(default = 5)]
+    max_attempts: u32,
+    // delay before the first retry, which doubles for each retry unless Google tells how long to wait
+    #[builder(default = time::Duration::from_secs(1))]
+    retry_delay: time::Duration,
+    // retries are given up once they'd exceed this time since the first attempt
+    #[builder(default = time::Duration::from_secs(60))]
+    retry_deadline: time::Duration,
+    // time to wait for connecting, and for a whole request including reading the response, or
+    // none for defaults of reqwest
+    #[builder(default)]
+    connect_timeout: Option<time::Duration>,
+    #[builder(default)]
+    timeout: Option<time::Duration>,
+    // proxy for all requests, or none to respect HTTPS_PROXY and NO_PROXY environment variables
+    #[builder(default, setter(into))]
+    proxy: Option<String>,
+    // PEM file of root certificates to trust in addition to the system's, e.g. of a proxy which
+    // intercepts TLS
+    #[builder(default, setter(into))]
+    root_certificates: Option<PathBuf>,
+    // HTTP client shared with other clients, to reuse connections; the options above are ignored
+    #[builder(default)]
+    http_client: Option<HttpClient>,
+    #[builder(default, setter(skip))]
+    built_http_client: OnceLock<HttpClient>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct Cache {
     pub secret: String,
     pub created: DateTime<Utc>,
+    // to obtain a new token without going through the OAuth flow again
+    #[serde(default)]
+    pub refresh_token: Option<String>,
 }
 
 impl GoogleSheetsReadOnlyClient {
@@ -56,56 +130,346 @@ impl GoogleSheetsReadOnlyClient {
         sheet_name: impl Into<String>,
         range: impl Into<String>,
     ) -> Result<Spreadsheet, Error> {
-        let mut id = sheet_id.into();
+        let id = Self::parse_sheet_id(sheet_id)?;
+        let sheet_name = sheet_name.into();
+        let range = range.into();
+
+        // the version is taken before fetching, so that changes made in between are fetched next
+        // time rather than being missed. If it can't be taken, e.g. Google Drive API is not
+        // enabled, the cache is not used, but still stored to be used in offline mode.
+        let version = match &self.cache {
+            Some(cache) => match self.get_version(&id) {
+                Ok(version) => {
+                    if let Ok(Some(entry)) = cache.load(&id, &sheet_name, &range) {
+                        // spreadsheets fetched with other fields may lack some of them
+                        if entry.version.as_ref() == Some(&version) && entry.fields == self.fields {
+                            return Ok(entry.spreadsheet);
+                        }
+                    }
+                    Some(version)
+                }
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        let mut query = vec![
+            ("includeGridData", "true".to_string()),
+            ("ranges", format!("{}!{}", sheet_name, range)),
+        ];
+        if !self.fields.is_empty() {
+            query.push(("fields", self.fields.clone()));
+        }
+
+        let response = self.send(
+            |http, token| {
+                http.get(format!("{}{}", self.content_url, id))
+                    .query(&query)
+                    .header("Authorization", format!("Bearer {}", token))
+            },
+            true,
+        )?;
+
+        // deserialized as it's read, without holding the whole response in memory
+        let spreadsheet = serde_json::from_reader(response)?;
+
+        if let Some(cache) = &self.cache {
+            // failing to cache only costs fetching again next time
+            let _ = cache.store(
+                &id,
+                &sheet_name,
+                &range,
+                &spreadsheet,
+                version,
+                &self.fields,
+            );
+        }
+        Ok(spreadsheet)
+    }
+
+    /// Fetches values only, without formatting metadata of cells, which is much smaller than
+    /// [`Self::get`] for large ranges.
+    pub fn get_values(
+        &self,
+        sheet_id: impl Into<String>,
+        sheet_name: impl Into<String>,
+        range: impl Into<String>,
+        value_render_option: ValueRenderOption,
+        date_time_render_option: DateTimeRenderOption,
+    ) -> Result<ValueRange, Error> {
+        let id = Self::parse_sheet_id(sheet_id)?;
+
+        // the range is a path segment, which needs to be percent-encoded
+        let mut url = Url::parse(&format!("{}{}/values", self.content_url, id))?;
+        url.path_segments_mut()
+            .map_err(|_| InvalidSheetId)?
+            .push(&format!("{}!{}", sheet_name.into(), range.into()));
+
+        let response = self.send(
+            |http, token| {
+                http.get(url.as_str())
+                    .query(&[
+                        ("majorDimension", "ROWS"),
+                        ("valueRenderOption", value_render_option.as_str()),
+                        ("dateTimeRenderOption", date_time_render_option.as_str()),
+                    ])
+                    .header("Authorization", format!("Bearer {}", token))
+            },
+            true,
+        )?;
+
+        Ok(serde_json::from_reader(response)?)
+    }
+
+    // Returns version of the spreadsheet file, which is much cheaper to get than its content.
+    fn get_version(&self, id: &str) -> Result<String, Error> {
+        let text = self
+            .send(
+                |http, token| {
+                    http.get(format!("{}{}", self.drive_url, id))
+                        .query(&[("fields", "version"), ("supportsAllDrives", "true")])
+                        .header("Authorization", format!("Bearer {}", token))
+                },
+                true,
+            )?
+            .text()?;
+        let value: Value = serde_json::from_str(&text)?;
+        value["version"]
+            .as_str()
+            .map(|v| v.to_string())
+            .ok_or(UnexpectedResponse(text))
+    }
+
+    /// Applies changes to the sheet identified by `grid_id` in a single `batchUpdate` request,
+    /// which is atomic; either all changes are applied or none of them. Returns the range of the
+    /// sheet after the changes are applied.
+    pub fn batch_update(
+        &self,
+        sheet_id: impl Into<String>,
+        sheet_name: impl Into<String>,
+        range: impl Into<String>,
+        grid_id: i32,
+        changes: &[Change],
+    ) -> Result<Spreadsheet, Error> {
+        let id = Self::parse_sheet_id(sheet_id)?;
+        let requests = changes
+            .iter()
+            .map(|change| change.to_request(grid_id))
+            .collect::<Vec<_>>();
+
+        let mut url = Url::parse(&format!("{}{}:batchUpdate", self.content_url, id))?;
+        if !self.fields.is_empty() {
+            url.query_pairs_mut()
+                .append_pair("fields", &format!("updatedSpreadsheet({})", self.fields));
+        }
+
+        let text = self.post(
+            url.to_string(),
+            json!({
+                "requests": requests,
+                "includeSpreadsheetInResponse": true,
+                "responseIncludeGridData": true,
+                "responseRanges": [format!("{}!{}", sheet_name.into(), range.into())],
+            }),
+        )?;
+        let response: BatchUpdateSpreadsheetResponse = serde_json::from_str(&text)?;
+
+        response
+            .updated_spreadsheet
+            .ok_or_else(|| UnexpectedResponse(text))
+    }
+
+    // Requests to write are not idempotent, hence only retried when they are rate-limited, which
+    // means they have not been applied.
+    fn post(&self, url: String, body: Value) -> Result<String, Error> {
+        let response = self.send(
+            |http, token| {
+                http.post(&url)
+                    .header("Authorization", format!("Bearer {}", token))
+                    .json(&body)
+            },
+            false,
+        )?;
+
+        Ok(response.text()?)
+    }
+
+    // Sends a request built by `request` with an access token, and retries it with jittered
+    // exponential backoff while it's rate-limited, or fails on Google's side if `idempotent`, within
+    // the max attempts and the deadline. A token rejected, e.g. revoked, is renewed once.
+    fn send(
+        &self,
+        request: impl Fn(&HttpClient, &str) -> RequestBuilder,
+        idempotent: bool,
+    ) -> Result<Response, Error> {
+        let http = self.get_http_client()?;
+        let started = Instant::now();
+        let mut token = self.get_token()?;
+        let mut renewed = false;
+        let mut attempts = 0;
+        loop {
+            let response = request(http, &token).send()?;
+
+            // a token given as is can't be renewed
+            if response.status() == StatusCode::UNAUTHORIZED
+                && !renewed
+                && self.access_token.is_none()
+            {
+                token = self.renew_token()?;
+                renewed = true;
+                continue;
+            }
+            attempts += 1;
+
+            let retryable = match response.status() {
+                StatusCode::TOO_MANY_REQUESTS => true,
+                StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => idempotent,
+                _ => false,
+            };
+            if !retryable || attempts >= self.max_attempts {
+                return Self::check_response(response);
+            }
+
+            let delay = get_retry_after(&response)
+                .unwrap_or_else(|| get_backoff_delay(self.retry_delay, attempts));
+            if started.elapsed() + delay > self.retry_deadline {
+                return Self::check_response(response);
+            }
+            thread::sleep(delay);
+        }
+    }
+
+    /// Returns the HTTP client, which can be shared with other clients to reuse connections.
+    pub fn http_client(&self) -> Result<HttpClient, Error> {
+        Ok(self.get_http_client()?.clone())
+    }
+
+    fn get_http_client(&self) -> Result<&HttpClient, Error> {
+        if let Some(http) = &self.http_client {
+            return Ok(http);
+        }
+        if let Some(http) = self.built_http_client.get() {
+            return Ok(http);
+        }
+
+        // following redirects would open the token exchange up to SSRF vulnerabilities
+        let mut builder = HttpClient::builder().redirect(Policy::none());
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(path) = &self.root_certificates {
+            // certificates are added one by one, as a bundle is not parsed by every TLS backend
+            let pem = fs::read_to_string(path)?;
+            for cert in pem.split_inclusive("-----END CERTIFICATE-----") {
+                if cert.contains("-----BEGIN CERTIFICATE-----") {
+                    builder = builder.add_root_certificate(Certificate::from_pem(cert.as_bytes())?);
+                }
+            }
+        }
+
+        let http = builder.build()?;
+        Ok(self.built_http_client.get_or_init(|| http))
+    }
+
+    // Sends a request of the OAuth flow with the same HTTP client as other requests.
+    fn send_oauth(
+        &self,
+        request: HttpRequest,
+    ) -> Result<HttpResponse, oauth2::reqwest::Error<reqwest::Error>> {
+        let http = self
+            .get_http_client()
+            .map_err(|err| oauth2::reqwest::Error::Other(err.to_string()))?;
+        let mut response = http
+            .request(request.method, request.url.as_str())
+            .headers(request.headers)
+            .body(request.body)
+            .send()
+            .map_err(oauth2::reqwest::Error::Reqwest)?;
+
+        let mut body = Vec::new();
+        response
+            .read_to_end(&mut body)
+            .map_err(oauth2::reqwest::Error::Io)?;
+        Ok(HttpResponse {
+            status_code: response.status(),
+            headers: response.headers().to_owned(),
+            body,
+        })
+    }
+
+    /// Returns spreadsheet ID, which is either given as is, or extracted from its URL.
+    pub fn parse_sheet_id(sheet_id: impl Into<String>) -> Result<String, Error> {
+        let id = sheet_id.into();
         if id.starts_with("https://") {
-            id = Url::parse(&id)?
+            return Ok(Url::parse(&id)?
                 .path_segments()
                 .ok_or(InvalidSheetId)?
                 .collect::<Vec<&str>>()
                 .get(2)
                 .ok_or(InvalidSheetId)?
-                .to_string();
+                .to_string());
         }
 
-        let response = reqwest::blocking::Client::new()
-            .get(format!("{}{}", self.content_url, id))
-            .query(&[
-                ("includeGridData", "true"),
-                (
-                    "ranges",
-                    format!("{}!{}", sheet_name.into(), range.into()).as_str(),
-                ),
-            ])
-            .header("Authorization", format!("Bearer {}", self.get_token()?))
-            .send()?;
+        Ok(id)
+    }
 
-        if !response.status().is_success() {
-            return Err(UnexpectedResponse(response.text().unwrap_or_else(|_| {
-                "Unexpected response with no explanation from Google".to_string()
-            })));
+    fn check_response(response: Response) -> Result<Response, Error> {
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::from_response(
+                status.as_u16(),
+                response.text().unwrap_or_else(|_| {
+                    "Unexpected response with no explanation from Google".to_string()
+                }),
+            ));
         }
 
-        let text = response.text()?;
-        Ok(serde_json::from_str(&text)?)
+        Ok(response)
     }
 
-    fn get_access_token_cache_path() -> PathBuf {
-        let mut path_buf: PathBuf = temp_dir();
-        path_buf.push("access_token.json");
+    fn get_access_token_cache_path(&self) -> PathBuf {
+        let mut path_buf: PathBuf = self.access_token_cache_dir.clone();
+        // a read-only token can't be used for writing, hence cache them separately
+        if self.read_only {
+            path_buf.push("access_token.json");
+        } else {
+            path_buf.push("access_token_rw.json");
+        }
         path_buf
     }
 
     fn get_token(&self) -> Result<String, Error> {
-        if self.cache_access_token {
-            if let Ok(f) = &File::open(Self::get_access_token_cache_path()) {
-                let mut de = Deserializer::from_reader(f);
-                let cache = Cache::deserialize(&mut de)?;
-                if Utc::now().sub(Duration::minutes(59)) < cache.created {
-                    return Ok(cache.secret);
-                }
+        if let Some(token) = &self.access_token {
+            return Ok(token.clone());
+        }
+
+        let cache = self.read_token_cache();
+        if let Some(cache) = &cache {
+            if Utc::now().sub(Duration::minutes(59)) < cache.created {
+                return Ok(cache.secret.clone());
             }
         }
 
+        self.grant_token(cache.and_then(|cache| cache.refresh_token))
+    }
+
+    // Obtains a new token regardless of the cached one, which has been rejected.
+    fn renew_token(&self) -> Result<String, Error> {
+        let refresh_token = self
+            .read_token_cache()
+            .and_then(|cache| cache.refresh_token);
+        self.grant_token(refresh_token)
+    }
+
+    // Obtains a token with the refresh token if any, or through the OAuth flow otherwise, and
+    // caches it.
+    fn grant_token(&self, refresh_token: Option<String>) -> Result<String, Error> {
         let client = BasicClient::new(
             ClientId::new(self.client_id.clone()),
             Some(ClientSecret::new(self.client_secret.clone())),
@@ -114,12 +478,36 @@ impl GoogleSheetsReadOnlyClient {
         )
         .set_redirect_uri(RedirectUrl::new(format!("http://localhost:{}", self.port))?);
 
+        if let Some(refresh_token) = refresh_token {
+            // the refresh token may have been revoked, then falls back to the OAuth flow
+            if let Ok(token) = client
+                .exchange_refresh_token(&RefreshToken::new(refresh_token.clone()))
+                .request(|request| self.send_oauth(request))
+            {
+                // a new refresh token is not issued when refreshing
+                return self.store_token(&token, Some(refresh_token));
+            }
+        }
+
         let (authorize_url, _) = client
             .authorize_url(CsrfToken::new_random)
             .add_scopes(vec![
                 Scope::new("https://www.googleapis.com/auth/drive.readonly".to_string()),
-                Scope::new("https://www.googleapis.com/auth/spreadsheets.readonly".to_string()),
+                Scope::new(if self.read_only {
+                    "https://www.googleapis.com/auth/spreadsheets.readonly".to_string()
+                } else {
+                    "https://www.googleapis.com/auth/spreadsheets".to_string()
+                }),
             ])
+            // to be issued a refresh token, which is only worth it when tokens are cached
+            .add_extra_param(
+                "access_type",
+                if self.cache_access_token {
+                    "offline"
+                } else {
+                    "online"
+                },
+            )
             .url();
 
         if open::that(authorize_url.to_string()).is_err() {
@@ -152,7 +540,7 @@ impl GoogleSheetsReadOnlyClient {
             let code_pair = url
                 .query_pairs()
                 .find(|pair| {
-                    let &(ref key, _) = pair;
+                    let (key, _) = pair;
                     key == "code"
                 })
                 .ok_or(CodeMissing)?;
@@ -167,15 +555,36 @@ impl GoogleSheetsReadOnlyClient {
                 message
             );
             stream.write_all(response.as_bytes())?;
-            token = client.exchange_code(code).request(http_client)?;
+            token = client
+                .exchange_code(code)
+                .request(|request| self.send_oauth(request))?;
         }
 
         if token.access_token().secret() == "placeholder" {
             return Err(UnexpectedToken);
         }
 
+        let refresh_token = token.refresh_token().map(|t| t.secret().clone());
+        self.store_token(&token, refresh_token)
+    }
+
+    fn read_token_cache(&self) -> Option<Cache> {
+        if !self.cache_access_token {
+            return None;
+        }
+
+        let f = File::open(self.get_access_token_cache_path()).ok()?;
+        Cache::deserialize(&mut Deserializer::from_reader(f)).ok()
+    }
+
+    // Caches the token if configured so, and returns its secret.
+    fn store_token(
+        &self,
+        token: &BasicTokenResponse,
+        refresh_token: Option<String>,
+    ) -> Result<String, Error> {
         if self.cache_access_token {
-            let file = File::create(Self::get_access_token_cache_path())?;
+            let file = File::create(self.get_access_token_cache_path())?;
             let mut perms = file.metadata()?.permissions();
 
             #[cfg(target_family = "windows")]
@@ -195,9 +604,214 @@ impl GoogleSheetsReadOnlyClient {
                 &Cache {
                     secret: token.access_token().secret().clone(),
                     created: Utc::now(),
+                    refresh_token,
                 },
             )?;
         }
         Ok(token.access_token().secret().clone())
     }
 }
+
+// Returns how long Google tells to wait, either in seconds or until an HTTP date.
+fn get_retry_after(response: &Response) -> Option<time::Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.parse() {
+        return Some(time::Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+// Doubles the delay for each attempt, and randomizes it between the half and the whole, so that
+// clients rate-limited at the same time won't retry at the same time again.
+fn get_backoff_delay(delay: time::Duration, attempts: u32) -> time::Duration {
+    let delay = delay * 2u32.pow(attempts.clamp(1, 16) - 1);
+    let random = RandomState::new().build_hasher().finish();
+    delay / 2 + delay.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache::SpreadsheetCache,
+        client::{get_backoff_delay, GoogleSheetsReadOnlyClient},
+        error::Error,
+    };
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use std::{
+        env::temp_dir,
+        fs,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+        thread::JoinHandle,
+        time::Duration,
+    };
+
+    // Serves responses, given as status line and headers along with body, in order for each
+    // connection, and returns the number of requests served.
+    fn serve(responses: Vec<(&'static str, &'static str)>) -> (String, JoinHandle<usize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut requests = 0;
+            for (head, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                write!(
+                    stream,
+                    "{}\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    head,
+                    body.len(),
+                    body,
+                )
+                .unwrap();
+                requests += 1;
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn client(url: &str, max_attempts: u32) -> GoogleSheetsReadOnlyClient {
+        GoogleSheetsReadOnlyClient::builder()
+            .client_id("id")
+            .client_secret("secret")
+            .content_url(url)
+            .access_token("token")
+            .max_attempts(max_attempts)
+            .retry_delay(Duration::from_millis(1))
+            .build()
+    }
+
+    #[test]
+    fn test_retry() {
+        let (url, handle) = serve(vec![
+            ("HTTP/1.1 503 Service Unavailable\r\nretry-after: 0", ""),
+            ("HTTP/1.1 429 Too Many Requests", "{}"),
+            ("HTTP/1.1 200 OK", r#"{"spreadsheetId":"id"}"#),
+        ]);
+
+        let spreadsheet = client(&url, 3).get("id", "Sheet1", "A1:B2").unwrap();
+        assert_eq!(Some("id".to_string()), spreadsheet.spreadsheet_id);
+        assert_eq!(3, handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_get_without_version() {
+        let (url, handle) = serve(vec![
+            (
+                "HTTP/1.1 403 Forbidden",
+                r#"{"error":{"code":403,"status":"PERMISSION_DENIED","message":"Google Drive API has not been used in project 1 before or it is disabled."}}"#,
+            ),
+            ("HTTP/1.1 200 OK", r#"{"spreadsheetId":"id"}"#),
+        ]);
+        let client = GoogleSheetsReadOnlyClient::builder()
+            .client_id("id")
+            .client_secret("secret")
+            .content_url(&url)
+            .drive_url(&url)
+            .access_token("token")
+            .cache(SpreadsheetCache::new(
+                temp_dir().join("libgsqlite-test-version"),
+            ))
+            .build();
+
+        let spreadsheet = client.get("id", "Sheet1", "A1:B2").unwrap();
+        assert_eq!(Some("id".to_string()), spreadsheet.spreadsheet_id);
+        assert_eq!(2, handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_give_up_retry() {
+        let (url, handle) = serve(vec![
+            ("HTTP/1.1 503 Service Unavailable", ""),
+            ("HTTP/1.1 503 Service Unavailable", "busy"),
+        ]);
+
+        assert!(client(&url, 2).get("id", "Sheet1", "A1:B2").is_err());
+        assert_eq!(2, handle.join().unwrap());
+
+        // not rate-limited, hence not retried
+        let (url, handle) = serve(vec![("HTTP/1.1 404 Not Found", "")]);
+
+        assert!(client(&url, 2).get("id", "Sheet1", "A1:B2").is_err());
+        assert_eq!(1, handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_renew_token() {
+        let dir = temp_dir().join("libgsqlite-test-token");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access_token.json");
+        fs::write(
+            &path,
+            json!({"secret": "old", "created": Utc::now(), "refresh_token": "refresh"}).to_string(),
+        )
+        .unwrap();
+
+        let (url, handle) = serve(vec![
+            ("HTTP/1.1 401 Unauthorized", ""),
+            (
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json",
+                r#"{"access_token": "new", "token_type": "Bearer", "expires_in": 3599}"#,
+            ),
+            ("HTTP/1.1 200 OK", r#"{"spreadsheetId":"id"}"#),
+        ]);
+        let client = GoogleSheetsReadOnlyClient::builder()
+            .client_id("id")
+            .client_secret("secret")
+            .google_token_url(format!("{}token", url))
+            .content_url(&url)
+            .cache_access_token(true)
+            .access_token_cache_dir(&dir)
+            .build();
+
+        assert!(client.get("id", "Sheet1", "A1:B2").is_ok());
+        assert_eq!(3, handle.join().unwrap());
+
+        let cache: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!("new", cache["secret"]);
+        assert_eq!("refresh", cache["refresh_token"]);
+    }
+
+    #[test]
+    fn test_timeout() {
+        // accepts a connection, but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || listener.accept().unwrap());
+
+        let client = GoogleSheetsReadOnlyClient::builder()
+            .client_id("id")
+            .client_secret("secret")
+            .content_url(url)
+            .access_token("token")
+            .timeout(Some(Duration::from_millis(100)))
+            .build();
+
+        assert!(matches!(
+            client.get("id", "Sheet1", "A1:B2"),
+            Err(Error::FailedRequest(e)) if e.is_timeout()
+        ));
+        drop(handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_get_backoff_delay() {
+        let delay = Duration::from_secs(1);
+        for attempts in 1..5 {
+            let backoff = get_backoff_delay(delay, attempts);
+            assert!(delay * 2u32.pow(attempts - 1) / 2 <= backoff);
+            assert!(backoff <= delay * 2u32.pow(attempts - 1));
+        }
+    }
+}
@@ -1,8 +1,29 @@
 use oauth2::{
     basic::BasicErrorResponseType, url::ParseError, RequestTokenError, StandardErrorResponse,
 };
+use serde::Deserialize;
+use serde_json::Value;
 use thiserror::Error;
 
+/// Error returned by Google in the body of an unsuccessful response.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GoogleError {
+    /// HTTP status code.
+    pub code: u16,
+    /// Canonical error code, e.g. `NOT_FOUND`.
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub details: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: GoogleError,
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(transparent)]
@@ -25,10 +46,83 @@ pub enum Error {
     UnexpectedToken,
     #[error("Unexpected response: {0}")]
     UnexpectedResponse(String),
+    #[error("Not found: {}", .0.message)]
+    NotFound(GoogleError),
+    #[error("Permission denied: {}", .0.message)]
+    PermissionDenied(GoogleError),
+    #[error("Rate limited: {}", .0.message)]
+    RateLimited(GoogleError),
+    #[error("Unauthenticated: {}", .0.message)]
+    Unauthenticated(GoogleError),
+    #[error("Invalid range: {}", .0.message)]
+    InvalidRange(GoogleError),
+    #[error("Google API error {} {}: {}", .0.code, .0.status, .0.message)]
+    Api(GoogleError),
     #[error("Invalid redirect URL")]
     InvalidRedirectUrl,
     #[error("Missing code")]
     CodeMissing,
     #[error("Invalid sheet ID")]
     InvalidSheetId,
+    #[error("Cache directory {0} is not private to the user")]
+    InsecureCacheDir(String),
+}
+
+impl Error {
+    /// Classifies an unsuccessful response by its status code, along with the error in its body.
+    /// The body is kept as the message if it isn't an error of Google APIs.
+    pub fn from_response(code: u16, body: String) -> Self {
+        let error = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(response) => response.error,
+            Err(_) => GoogleError {
+                code,
+                status: "".to_string(),
+                message: body,
+                details: Vec::new(),
+            },
+        };
+
+        match code {
+            401 => Error::Unauthenticated(error),
+            404 => Error::NotFound(error),
+            429 => Error::RateLimited(error),
+            // exceeding quota used to be told with 403
+            403 if error.status == "RESOURCE_EXHAUSTED" => Error::RateLimited(error),
+            403 => Error::PermissionDenied(error),
+            400 if error.message.starts_with("Unable to parse range") => Error::InvalidRange(error),
+            _ => Error::Api(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+
+    #[test]
+    fn test_from_response() {
+        let body = r#"{
+            "error": {
+                "code": 400,
+                "message": "Unable to parse range: Sheet1!A1:B",
+                "status": "INVALID_ARGUMENT"
+            }
+        }"#;
+        assert!(matches!(
+            Error::from_response(400, body.to_string()),
+            Error::InvalidRange(e) if e.message == "Unable to parse range: Sheet1!A1:B"
+        ));
+
+        let body =
+            r#"{"error": {"code": 403, "message": "Denied", "status": "PERMISSION_DENIED"}}"#;
+        assert!(matches!(
+            Error::from_response(403, body.to_string()),
+            Error::PermissionDenied(e) if e.status == "PERMISSION_DENIED"
+        ));
+
+        assert!(matches!(
+            Error::from_response(502, "Bad Gateway".to_string()),
+            Error::Api(e) if e.code == 502 && e.message == "Bad Gateway"
+        ));
+    }
 }
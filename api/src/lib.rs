@@ -1,5 +1,12 @@
+pub mod cache;
+pub mod change;
 pub mod client;
 pub mod error;
+pub mod value_range;
 
 // re-export
-pub use google_sheets4::api::{CellData, RowData};
+pub use google_sheets4::api::{
+    CellData, CellFormat, ErrorValue, ExtendedValue, Link, NumberFormat, RowData, Sheet,
+    Spreadsheet, TextFormat, TextFormatRun,
+};
+pub use reqwest::blocking::Client as HttpClient;
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How values are rendered by `spreadsheets.values.get`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ValueRenderOption {
+    /// Values as displayed in the UI, always strings.
+    FormattedValue,
+    /// Values as they are, e.g. `0.5` for a cell displayed as `50%`.
+    UnformattedValue,
+    /// Formulas rather than their results, and unformatted values for other cells.
+    Formula,
+}
+
+impl ValueRenderOption {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValueRenderOption::FormattedValue => "FORMATTED_VALUE",
+            ValueRenderOption::UnformattedValue => "UNFORMATTED_VALUE",
+            ValueRenderOption::Formula => "FORMULA",
+        }
+    }
+}
+
+/// How dates, times and durations are rendered, unless values are rendered as formatted.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DateTimeRenderOption {
+    /// Days since December 30th 1899, e.g. `45000.5`.
+    SerialNumber,
+    /// Strings as displayed in the UI.
+    FormattedString,
+}
+
+impl DateTimeRenderOption {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DateTimeRenderOption::SerialNumber => "SERIAL_NUMBER",
+            DateTimeRenderOption::FormattedString => "FORMATTED_STRING",
+        }
+    }
+}
+
+/// Values in a range, row by row. Unlike `google_sheets4::api::ValueRange`, values are kept as
+/// JSON so that numbers and booleans are not lost.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueRange {
+    pub range: Option<String>,
+    pub major_dimension: Option<String>,
+    // trailing empty rows and cells are omitted
+    #[serde(default)]
+    pub values: Vec<Vec<Value>>,
+}
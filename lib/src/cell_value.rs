@@ -1,35 +1,236 @@
-use crate::sqlite3ext::{sqlite3_api_routines, sqlite3_context};
-use google_sheets_api::CellData;
+use crate::{
+    error::SheetError::CellError,
+    sqlite3ext::{
+        sqlite3_api_routines, sqlite3_context, sqlite3_value, SQLITE_BLOB, SQLITE_ERROR,
+        SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_OK, SQLITE_TEXT,
+    },
+};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use google_sheets_api::{CellData, ExtendedValue};
+use serde_json::{json, Value};
 use std::{
-    ffi::CString,
+    ffi::{CStr, CString},
     os::raw::{c_char, c_int, c_void},
 };
 
-#[derive(Debug)]
-enum CellValue {
+/// How cells formatted as dates, times, or date-times are converted.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum DateTimeMode {
+    /// Text in the format of SQLite date and time functions, e.g. `2024-01-31 13:45:00`.
+    #[default]
+    Iso8601,
+    /// Julian day number, like `julianday()` of SQLite.
+    JulianDay,
+    /// Seconds since Unix epoch, like `unixepoch()` of SQLite.
+    UnixEpoch,
+    /// Days since December 30th 1899 as stored in the sheet, e.g. `45322.5`.
+    Serial,
+    /// Text as displayed in the sheet.
+    Formatted,
+}
+
+/// How boolean cells, e.g. checkboxes, are converted.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum BooleanMode {
+    /// 1 for true, and 0 for false, like boolean expressions of SQLite.
+    #[default]
+    Integer,
+    /// Text as displayed in the sheet, `TRUE` or `FALSE`.
+    Text,
+}
+
+/// How cells with formula errors, e.g. `#N/A` or `#DIV/0!`, are converted.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ErrorMode {
+    /// Text as displayed in the sheet, e.g. `#N/A`.
+    #[default]
+    Text,
+    /// NULL, so that numeric columns only contain numbers.
+    Null,
+    /// Fails the query, telling the type and the message of the error along with the cell.
+    Fail,
+}
+
+/// What cells are converted into.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum CellMode {
+    /// Values, converted according to the other options.
+    #[default]
+    Value,
+    /// Whole cells as JSON text, including values, formats, notes, hyperlinks, and so on.
+    Json,
+}
+
+/// Options of converting cells into values.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ValueOptions {
+    pub mode: CellMode,
+    pub date_time: DateTimeMode,
+    pub boolean: BooleanMode,
+    pub errors: ErrorMode,
+    /// Removes leading and trailing whitespaces from text.
+    pub trim: bool,
+    /// Converts empty text into NULL, e.g. results of formulas like `=IF(A1, "x", "")`.
+    pub empty_as_null: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum CellValue {
     Str(String),
     Float(f64),
     Int(i64),
     Empty,
+    /// A formula error with `ERRORS 'fail'`, which fails the query reading it: the error as
+    /// displayed, the cell, and the message of the error.
+    Error(String, String, String),
+}
+
+impl CellValue {
+    /// Reads a value passed by SQLite, e.g. a column value of `UPDATE` statement. Blobs are read
+    /// as text.
+    pub unsafe fn from_raw(api: *mut sqlite3_api_routines, value: *mut sqlite3_value) -> Self {
+        match ((*api).value_type.unwrap())(value) {
+            SQLITE_INTEGER => CellValue::Int(((*api).value_int64.unwrap())(value)),
+            SQLITE_FLOAT => CellValue::Float(((*api).value_double.unwrap())(value)),
+            SQLITE_TEXT | SQLITE_BLOB => {
+                let raw = ((*api).value_text.unwrap())(value);
+                if raw.is_null() {
+                    CellValue::Empty
+                } else {
+                    CellValue::Str(
+                        CStr::from_ptr(raw as *const c_char)
+                            .to_string_lossy()
+                            .to_string(),
+                    )
+                }
+            }
+            _ => CellValue::Empty,
+        }
+    }
+
+    /// Reads a value returned by `spreadsheets.values.get`. Booleans are read as text as they are
+    /// displayed, `TRUE` or `FALSE`, and empty strings as empty.
+    pub fn from_json(value: &Value) -> Self {
+        match value {
+            Value::String(s) if s.is_empty() => CellValue::Empty,
+            Value::String(s) => CellValue::Str(s.to_string()),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => CellValue::Int(i),
+                None => CellValue::Float(n.as_f64().unwrap_or_default()),
+            },
+            Value::Bool(b) => CellValue::Str(b.to_string().to_uppercase()),
+            _ => CellValue::Empty,
+        }
+    }
+
+    /// Converts into a value to be written to a sheet. Empty clears the cell.
+    pub fn to_json(&self) -> Value {
+        match self {
+            CellValue::Str(s) => Value::from(s.as_str()),
+            CellValue::Float(f) => Value::from(*f),
+            CellValue::Int(i) => Value::from(*i),
+            CellValue::Empty => Value::Null,
+            CellValue::Error(error, _, _) => Value::from(error.as_str()),
+        }
+    }
 }
 
-pub unsafe fn yield_cell_value(
+impl From<CellValue> for CellData {
+    fn from(value: CellValue) -> Self {
+        let (effective_value, formatted_value) = match value {
+            CellValue::Str(s) | CellValue::Error(s, _, _) => (
+                ExtendedValue {
+                    string_value: Some(s.clone()),
+                    ..Default::default()
+                },
+                s,
+            ),
+            CellValue::Float(f) => (
+                ExtendedValue {
+                    number_value: Some(f),
+                    ..Default::default()
+                },
+                f.to_string(),
+            ),
+            CellValue::Int(i) => (
+                ExtendedValue {
+                    number_value: Some(i as f64),
+                    ..Default::default()
+                },
+                i.to_string(),
+            ),
+            CellValue::Empty => return CellData::default(),
+        };
+
+        CellData {
+            effective_value: Some(effective_value),
+            formatted_value: Some(formatted_value),
+            ..Default::default()
+        }
+    }
+}
+
+/// Converts a value returned by `spreadsheets.values.get` into a cell, as if it's fetched with grid
+/// data.
+pub fn cell_data_from_json(value: &Value) -> CellData {
+    match value {
+        Value::Bool(b) => CellData {
+            effective_value: Some(ExtendedValue {
+                bool_value: Some(*b),
+                ..Default::default()
+            }),
+            formatted_value: Some(b.to_string().to_uppercase()),
+            ..Default::default()
+        },
+        _ => CellValue::from_json(value).into(),
+    }
+}
+
+/// Returns a value to SQLite, or fails with the error of the cell if it has a formula error.
+pub unsafe fn yield_value(
     p_context: *mut sqlite3_context,
     api: *mut sqlite3_api_routines,
-    value: Option<&CellData>,
-) {
-    match parse_value(value) {
+    value: &CellValue,
+) -> c_int {
+    match value {
         CellValue::Str(s) => {
             let (len, raw) = to_raw_string(s);
             ((*api).result_text.unwrap())(p_context, raw, len as c_int, Some(destructor))
         }
-        CellValue::Float(f) => ((*api).result_double.unwrap())(p_context, f),
-        CellValue::Int(i) => ((*api).result_int64.unwrap())(p_context, i),
+        CellValue::Float(f) => ((*api).result_double.unwrap())(p_context, *f),
+        CellValue::Int(i) => ((*api).result_int64.unwrap())(p_context, *i),
         CellValue::Empty => ((*api).result_null.unwrap())(p_context),
+        CellValue::Error(error, cell, message) => {
+            let err = CellError(error.clone(), cell.clone(), message.clone()).to_string();
+            let err = CString::new(err).unwrap_or_default();
+            ((*api).result_error.unwrap())(p_context, err.as_ptr(), -1);
+            return SQLITE_ERROR;
+        }
     }
+    SQLITE_OK
 }
 
-fn parse_value(value: Option<&CellData>) -> CellValue {
+pub fn parse_value(value: Option<&CellData>, options: &ValueOptions) -> CellValue {
+    if options.mode == CellMode::Json {
+        return value.map_or(CellValue::Empty, to_json_value);
+    }
+
+    match parse_cell(value, options) {
+        CellValue::Str(s) if options.trim => normalize_text(s.trim().to_string(), options),
+        CellValue::Str(s) => normalize_text(s, options),
+        value => value,
+    }
+}
+
+fn normalize_text(s: String, options: &ValueOptions) -> CellValue {
+    if options.empty_as_null && s.is_empty() {
+        CellValue::Empty
+    } else {
+        CellValue::Str(s)
+    }
+}
+
+fn parse_cell(value: Option<&CellData>, options: &ValueOptions) -> CellValue {
     if let Some(v) = value {
         if let (Some(formatted_str), Some(effective_value)) =
             (&v.formatted_value, &v.effective_value)
@@ -39,24 +240,39 @@ fn parse_value(value: Option<&CellData>) -> CellValue {
                 return CellValue::Str(str.to_string());
             }
 
-            if let Some(num) = &effective_value.number_value {
-                // if effective value is a number, but (1) it couldn't be parsed as i64 or f64, and
-                // (2) formatted value doesn't contain '%', it might be a Dates, Times, or DateTimes.
-                // Since it's difficult to parse google_sheets4::api::NumberFormat into
-                // chrono::format::strftime, returns formatted string as a cell representation
-                if (formatted_str.parse::<i64>().is_err() || formatted_str.parse::<f64>().is_err())
-                    && !formatted_str.contains('%')
-                {
-                    return CellValue::Str(formatted_str.to_string());
+            if let Some(error) = &effective_value.error_value {
+                match options.errors {
+                    ErrorMode::Null => return CellValue::Empty,
+                    // the cell is told by the sheet, which knows where the cell is
+                    ErrorMode::Fail => {
+                        return CellValue::Error(
+                            formatted_str.to_string(),
+                            String::new(),
+                            error.message.clone().unwrap_or_default(),
+                        )
+                    }
+                    ErrorMode::Text => {}
                 }
+            }
 
-                // number value is always f64, but if formatted string can be parse as i64, returns
-                // it instead because it's intuitive
-                if let Ok(v) = formatted_str.parse::<i64>() {
-                    return CellValue::Int(v);
+            if let Some(num) = &effective_value.number_value {
+                let format_type = v
+                    .effective_format
+                    .as_ref()
+                    .and_then(|f| f.number_format.as_ref())
+                    .and_then(|f| f.type_.as_deref());
+                if let Some(value) = parse_date_time(*num, format_type, options.date_time) {
+                    return value;
                 }
 
-                return CellValue::Float(*num);
+                return parse_number(*num, format_type, formatted_str);
+            }
+
+            if let Some(b) = effective_value.bool_value {
+                return match options.boolean {
+                    BooleanMode::Integer => CellValue::Int(b as i64),
+                    BooleanMode::Text => CellValue::Str(formatted_str.to_string()),
+                };
             }
 
             return CellValue::Str(formatted_str.to_string());
@@ -67,8 +283,280 @@ fn parse_value(value: Option<&CellData>) -> CellValue {
     CellValue::Empty
 }
 
-fn to_raw_string(s: String) -> (usize, *mut c_char) {
-    let cstr = CString::new(s.as_str().as_bytes()).unwrap();
+/// Suffixes of hidden columns exposing metadata of cells in each column, in the order they follow
+/// the columns of values.
+pub const METADATA_SUFFIXES: [&str; 5] = ["formula", "note", "hyperlink", "format", "runs"];
+
+/// Returns metadata of a cell in the order of `METADATA_SUFFIXES`, i.e. the formula, the note, the
+/// hyperlink, the format as JSON, and the text runs as JSON, each of which is empty if not set.
+pub fn parse_metadata(value: Option<&CellData>) -> [CellValue; 5] {
+    let text = |s: Option<String>| s.map_or(CellValue::Empty, CellValue::Str);
+
+    match value {
+        Some(v) => [
+            text(
+                v.user_entered_value
+                    .as_ref()
+                    .and_then(|v| v.formula_value.clone()),
+            ),
+            text(v.note.clone()),
+            text(v.hyperlink.clone()),
+            text(
+                v.effective_format
+                    .as_ref()
+                    .and_then(|f| serde_json::to_value(f).ok())
+                    .map(|f| remove_nulls(f).to_string()),
+            ),
+            text(parse_runs(v).map(|runs| runs.to_string())),
+        ],
+        None => [
+            CellValue::Empty,
+            CellValue::Empty,
+            CellValue::Empty,
+            CellValue::Empty,
+            CellValue::Empty,
+        ],
+    }
+}
+
+// Splits the text of a cell into runs, each of which has its text and link if any, e.g.
+// `[{"text":"See "},{"text":"docs","link":"https://..."}]`. A cell linked as a whole is a single run.
+// Returns none if the text has neither formats nor links.
+fn parse_runs(cell: &CellData) -> Option<Value> {
+    let text = cell.formatted_value.as_deref().unwrap_or_default();
+    let runs = match &cell.text_format_runs {
+        Some(runs) if !runs.is_empty() => runs,
+        _ => {
+            let link = cell.hyperlink.as_ref()?;
+            return Some(json!([{ "text": text, "link": link }]));
+        }
+    };
+
+    // indices are of UTF-16 code units, as in JavaScript
+    let units = text.encode_utf16().collect::<Vec<_>>();
+    let starts = runs
+        .iter()
+        .map(|run| (run.start_index.unwrap_or_default().max(0) as usize).min(units.len()))
+        .collect::<Vec<_>>();
+
+    let values = runs
+        .iter()
+        .enumerate()
+        .map(|(i, run)| {
+            let end = starts
+                .get(i + 1)
+                .copied()
+                .unwrap_or(units.len())
+                .max(starts[i]);
+            let mut value = json!({ "text": String::from_utf16_lossy(&units[starts[i]..end]) });
+            let link = run
+                .format
+                .as_ref()
+                .and_then(|f| f.link.as_ref())
+                .and_then(|l| l.uri.as_ref())
+                .or(cell.hyperlink.as_ref());
+            if let Some(link) = link {
+                value["link"] = json!(link);
+            }
+            value
+        })
+        .collect();
+    Some(Value::Array(values))
+}
+
+/// Returns the type of the effective value of a cell, i.e. `string`, `number`, `boolean`, or
+/// `error`, or none if it's empty.
+pub fn get_value_type(cell: &CellData) -> Option<&'static str> {
+    let value = cell.effective_value.as_ref()?;
+    if value.string_value.is_some() {
+        Some("string")
+    } else if value.number_value.is_some() {
+        Some("number")
+    } else if value.bool_value.is_some() {
+        Some("boolean")
+    } else if value.error_value.is_some() {
+        Some("error")
+    } else {
+        None
+    }
+}
+
+// Converts a whole cell into JSON text, or empty if nothing is set.
+fn to_json_value(cell: &CellData) -> CellValue {
+    match serde_json::to_value(cell).map(remove_nulls) {
+        Ok(Value::Object(map)) if map.is_empty() => CellValue::Empty,
+        Ok(v) => CellValue::Str(v.to_string()),
+        Err(_) => CellValue::Empty,
+    }
+}
+
+// Removes fields which are not set, as they are serialized as nulls.
+fn remove_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, remove_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(remove_nulls).collect()),
+        v => v,
+    }
+}
+
+// Decides the type of a number by its value and format rather than its formatted string, which
+// depends on the locale of the spreadsheet, e.g. `1,234.5` or `1.234,5`.
+fn parse_number(num: f64, format_type: Option<&str>, formatted_str: &str) -> CellValue {
+    match format_type {
+        // dates and times which are not converted are left as displayed
+        Some("DATE" | "TIME" | "DATE_TIME") => CellValue::Str(formatted_str.to_string()),
+        // amounts of money and ratios are real numbers even if they happen to be integral
+        Some("CURRENCY" | "PERCENT") => CellValue::Float(num),
+        _ if num.fract() == 0.0 && num.abs() < i64::MAX as f64 => CellValue::Int(num as i64),
+        _ => CellValue::Float(num),
+    }
+}
+
+// Converts a serial number, days since December 30th 1899 with time as fraction, if it's formatted
+// as a date, time, or date-time. Times without dates are always converted into text.
+fn parse_date_time(
+    serial: f64,
+    format_type: Option<&str>,
+    mode: DateTimeMode,
+) -> Option<CellValue> {
+    let format_type = format_type.filter(|t| matches!(*t, "DATE" | "TIME" | "DATE_TIME"))?;
+    match mode {
+        DateTimeMode::Formatted => return None,
+        DateTimeMode::Serial => return Some(CellValue::Float(serial)),
+        _ => {}
+    }
+
+    // serial numbers out of the range of dates are left as formatted, rather than overflowing;
+    // `Duration::seconds` panics unless milliseconds fit in i64
+    let seconds = (serial * 86400.0).round() as i64;
+    seconds.checked_mul(1000)?;
+    let date_time = NaiveDate::from_ymd_opt(1899, 12, 30)?
+        .and_hms_opt(0, 0, 0)?
+        .checked_add_signed(Duration::seconds(seconds))?;
+
+    let value = match (format_type, mode) {
+        // times of date-times are taken, as the cell may have a date as well, e.g. `=NOW()`
+        ("TIME", _) => CellValue::Str(date_time.format("%H:%M:%S").to_string()),
+        (_, DateTimeMode::JulianDay) => CellValue::Float(serial + 2415018.5),
+        (_, DateTimeMode::UnixEpoch) => CellValue::Int(date_time.timestamp()),
+        ("DATE", _) => CellValue::Str(date_time.format("%Y-%m-%d").to_string()),
+        _ => CellValue::Str(date_time.format("%Y-%m-%d %H:%M:%S").to_string()),
+    };
+    Some(value)
+}
+
+/// Converts a value into the one to be written to a cell, parsing text as the spreadsheet UI would
+/// for the cases below, since values are written as they are rather than entered by a user:
+///
+/// - Text is written as is to cells holding text, or formatted as plain text.
+/// - `TRUE` or `FALSE`, and 1 or 0 for boolean cells, are written as booleans.
+/// - Numbers like `1,234`, `$12.50`, or `12%` are written as numbers.
+/// - Text like `2024-01-31`, `2024-01-31 13:45:00`, or `13:45`, and numbers converted by
+///   `DATETIME 'julianday'`, `DATETIME 'unixepoch'`, or `DATETIME 'serial'`, are written as serial numbers to cells
+///   formatted as dates, times, or date-times, so that they stay dates.
+pub fn to_user_entered(
+    value: &CellValue,
+    cell: Option<&CellData>,
+    options: &ValueOptions,
+) -> Value {
+    let effective_value = cell.and_then(|c| c.effective_value.as_ref());
+    let is_boolean = effective_value.is_some_and(|v| v.bool_value.is_some());
+    let format_type = cell
+        .and_then(|c| c.effective_format.as_ref())
+        .and_then(|f| f.number_format.as_ref())
+        .and_then(|f| f.type_.as_deref());
+    let is_date_time = matches!(format_type, Some("DATE" | "TIME" | "DATE_TIME"));
+    // text is kept as is in cells holding text, or formatted as plain text, e.g. `01234`
+    let is_text =
+        format_type == Some("TEXT") || effective_value.is_some_and(|v| v.string_value.is_some());
+    if let (CellValue::Str(_), true) = (value, is_text) {
+        return value.to_json();
+    }
+
+    let serial = match (value, options.date_time) {
+        (CellValue::Str(s), _) => parse_date_time_text(s),
+        (CellValue::Int(i), DateTimeMode::UnixEpoch) => Some(*i as f64 / 86400.0 + 25569.0),
+        (CellValue::Float(f), DateTimeMode::UnixEpoch) => Some(f / 86400.0 + 25569.0),
+        (CellValue::Int(i), DateTimeMode::JulianDay) => Some(*i as f64 - 2415018.5),
+        (CellValue::Float(f), DateTimeMode::JulianDay) => Some(f - 2415018.5),
+        (CellValue::Int(i), DateTimeMode::Serial) => Some(*i as f64),
+        (CellValue::Float(f), DateTimeMode::Serial) => Some(*f),
+        _ => None,
+    };
+    if let Some(serial) = serial.filter(|_| is_date_time) {
+        return Value::from(serial);
+    }
+
+    match value {
+        CellValue::Int(b @ (0 | 1)) if is_boolean => Value::Bool(*b == 1),
+        CellValue::Str(s) if s.eq_ignore_ascii_case("true") => Value::Bool(true),
+        CellValue::Str(s) if s.eq_ignore_ascii_case("false") => Value::Bool(false),
+        CellValue::Str(s) => parse_number_text(s).map_or_else(|| value.to_json(), Value::from),
+        _ => value.to_json(),
+    }
+}
+
+// Parses a number with optional sign, currency symbol, grouping commas, and percent sign, in the
+// format of the en-US locale.
+fn parse_number_text(s: &str) -> Option<f64> {
+    let (s, percent) = match s.strip_suffix('%') {
+        Some(s) => (s, true),
+        None => (s, false),
+    };
+    let (s, sign) = match s.strip_prefix('-') {
+        Some(s) => (s, -1.0),
+        None => (s.strip_prefix('+').unwrap_or(s), 1.0),
+    };
+    let s = s.strip_prefix(['$', '€', '£', '¥']).unwrap_or(s);
+
+    let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+    let groups = integer.split(',').collect::<Vec<_>>();
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    let is_grouped = groups.len() == 1
+        || (matches!(groups[0].len(), 1..=3) && groups[1..].iter().all(|g| g.len() == 3));
+    if (integer.is_empty() && fraction.is_empty())
+        || !is_grouped
+        || !groups.iter().all(|g| is_digits(g))
+        || !is_digits(fraction)
+    {
+        return None;
+    }
+
+    let number = format!("0{}.{}0", groups.concat(), fraction)
+        .parse::<f64>()
+        .ok()?;
+    Some(sign * if percent { number / 100.0 } else { number })
+}
+
+// Parses a date, a date-time, or a time in the format of SQLite date and time functions into a
+// serial number.
+fn parse_date_time_text(s: &str) -> Option<f64> {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?.and_hms_opt(0, 0, 0)?;
+    let date_time = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        });
+    if let Some(date_time) = date_time {
+        return Some((date_time - epoch).num_seconds() as f64 / 86400.0);
+    }
+
+    let time = NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .ok()?;
+    Some(time.num_seconds_from_midnight() as f64 / 86400.0)
+}
+
+fn to_raw_string(s: &str) -> (usize, *mut c_char) {
+    let cstr = CString::new(s.as_bytes()).unwrap();
     let len = cstr.as_bytes().len();
     let raw = cstr.into_raw();
 
@@ -78,3 +566,385 @@ fn to_raw_string(s: String) -> (usize, *mut c_char) {
 unsafe extern "C" fn destructor(raw: *mut c_void) {
     drop(CString::from_raw(raw as *mut c_char));
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cell_value::{
+        cell_data_from_json, parse_metadata, parse_value, to_user_entered, BooleanMode, CellMode,
+        CellValue, DateTimeMode, ErrorMode, ValueOptions,
+    };
+    use google_sheets_api::{
+        CellData, CellFormat, ErrorValue, ExtendedValue, Link, NumberFormat, TextFormat,
+        TextFormatRun,
+    };
+    use serde_json::json;
+
+    fn date_cell(serial: f64, format_type: &str) -> CellData {
+        number_cell(serial, "1/31/2024", Some(format_type))
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let cell = CellData {
+            user_entered_value: Some(ExtendedValue {
+                formula_value: Some("=SUM(A1:A3)".to_string()),
+                ..Default::default()
+            }),
+            note: Some("checked".to_string()),
+            ..number_cell(6.0, "6", Some("NUMBER"))
+        };
+
+        assert_eq!(
+            [
+                CellValue::Str("=SUM(A1:A3)".into()),
+                CellValue::Str("checked".into()),
+                CellValue::Empty,
+                CellValue::Str(r#"{"numberFormat":{"type":"NUMBER"}}"#.into()),
+                CellValue::Empty,
+            ],
+            parse_metadata(Some(&cell))
+        );
+        assert_eq!(
+            [
+                CellValue::Empty,
+                CellValue::Empty,
+                CellValue::Empty,
+                CellValue::Empty,
+                CellValue::Empty
+            ],
+            parse_metadata(None)
+        );
+    }
+
+    #[test]
+    fn test_parse_runs() {
+        let link = |start, uri: Option<&str>| TextFormatRun {
+            start_index: start,
+            format: Some(TextFormat {
+                link: uri.map(|uri| Link {
+                    uri: Some(uri.to_string()),
+                }),
+                ..Default::default()
+            }),
+        };
+        let cell = CellData {
+            formatted_value: Some("See ドキュメント and FAQ".to_string()),
+            text_format_runs: Some(vec![
+                link(None, None),
+                link(Some(4), Some("https://example.com/docs")),
+                link(Some(10), None),
+                link(Some(15), Some("https://example.com/faq")),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            CellValue::Str(
+                r#"[{"text":"See "},{"link":"https://example.com/docs","text":"ドキュメント"},{"text":" and "},{"link":"https://example.com/faq","text":"FAQ"}]"#
+                    .into()
+            ),
+            parse_metadata(Some(&cell))[4]
+        );
+
+        let cell = CellData {
+            formatted_value: Some("docs".to_string()),
+            hyperlink: Some("https://example.com/docs".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            CellValue::Str(r#"[{"link":"https://example.com/docs","text":"docs"}]"#.into()),
+            parse_metadata(Some(&cell))[4]
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let options = ValueOptions {
+            mode: CellMode::Json,
+            ..Default::default()
+        };
+        let cell = CellData {
+            note: Some("checked".to_string()),
+            ..number_cell(6.0, "6", Some("NUMBER"))
+        };
+
+        assert_eq!(
+            CellValue::Str(
+                r#"{"effectiveFormat":{"numberFormat":{"type":"NUMBER"}},"effectiveValue":{"numberValue":6.0},"formattedValue":"6","note":"checked"}"#
+                    .into()
+            ),
+            parse_value(Some(&cell), &options)
+        );
+        assert_eq!(
+            CellValue::Empty,
+            parse_value(Some(&CellData::default()), &options)
+        );
+        assert_eq!(CellValue::Empty, parse_value(None, &options));
+    }
+
+    #[test]
+    fn test_normalize_text() {
+        let cell = |s: &str| CellData::from(CellValue::Str(s.to_string()));
+        let options = ValueOptions {
+            trim: true,
+            empty_as_null: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            CellValue::Str(" foo ".into()),
+            parse_value(Some(&cell(" foo ")), &ValueOptions::default())
+        );
+        assert_eq!(
+            CellValue::Str(String::new()),
+            parse_value(Some(&cell("")), &ValueOptions::default())
+        );
+        assert_eq!(
+            CellValue::Str("foo".into()),
+            parse_value(Some(&cell(" foo ")), &options)
+        );
+        assert_eq!(CellValue::Empty, parse_value(Some(&cell("  ")), &options));
+        assert_eq!(CellValue::Empty, parse_value(Some(&cell("")), &options));
+    }
+
+    #[test]
+    fn test_parse_boolean() {
+        let cell = cell_data_from_json(&json!(true));
+        assert_eq!(
+            CellValue::Int(1),
+            parse_value(Some(&cell), &ValueOptions::default())
+        );
+
+        let options = ValueOptions {
+            boolean: BooleanMode::Text,
+            ..Default::default()
+        };
+        assert_eq!(
+            CellValue::Str("TRUE".into()),
+            parse_value(Some(&cell), &options)
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let cell = CellData {
+            effective_value: Some(ExtendedValue {
+                error_value: Some(ErrorValue {
+                    type_: Some("N_A".to_string()),
+                    message: Some("Did not find value 'foo' in VLOOKUP evaluation.".to_string()),
+                }),
+                ..Default::default()
+            }),
+            formatted_value: Some("#N/A".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            CellValue::Str("#N/A".into()),
+            parse_value(Some(&cell), &ValueOptions::default())
+        );
+
+        let options = ValueOptions {
+            errors: ErrorMode::Null,
+            ..Default::default()
+        };
+        assert_eq!(CellValue::Empty, parse_value(Some(&cell), &options));
+
+        let options = ValueOptions {
+            errors: ErrorMode::Fail,
+            ..Default::default()
+        };
+        assert_eq!(
+            CellValue::Error(
+                "#N/A".into(),
+                String::new(),
+                "Did not find value 'foo' in VLOOKUP evaluation.".into()
+            ),
+            parse_value(Some(&cell), &options)
+        );
+    }
+
+    fn number_cell(num: f64, formatted: &str, format_type: Option<&str>) -> CellData {
+        CellData {
+            effective_value: Some(ExtendedValue {
+                number_value: Some(num),
+                ..Default::default()
+            }),
+            formatted_value: Some(formatted.to_string()),
+            effective_format: format_type.map(|t| CellFormat {
+                number_format: Some(NumberFormat {
+                    type_: Some(t.to_string()),
+                    pattern: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_number() {
+        let parse = |cell: CellData| parse_value(Some(&cell), &ValueOptions::default());
+
+        assert_eq!(CellValue::Int(42), parse(number_cell(42.0, "42", None)));
+        assert_eq!(
+            CellValue::Int(1234),
+            parse(number_cell(1234.0, "1,234", Some("NUMBER")))
+        );
+        assert_eq!(
+            CellValue::Float(1234.5),
+            parse(number_cell(1234.5, "1.234,5", Some("NUMBER")))
+        );
+        assert_eq!(CellValue::Float(2.5), parse(number_cell(2.5, "2.5", None)));
+        assert_eq!(
+            CellValue::Float(12.0),
+            parse(number_cell(12.0, "$12.00", Some("CURRENCY")))
+        );
+        assert_eq!(
+            CellValue::Float(0.12),
+            parse(number_cell(0.12, "12%", Some("PERCENT")))
+        );
+    }
+
+    #[test]
+    fn test_parse_date_time() {
+        let options = |date_time| ValueOptions {
+            date_time,
+            ..Default::default()
+        };
+        let iso = options(DateTimeMode::Iso8601);
+
+        assert_eq!(
+            CellValue::Str("2024-01-31".into()),
+            parse_value(Some(&date_cell(45322.0, "DATE")), &iso)
+        );
+        assert_eq!(
+            CellValue::Str("2024-01-31 13:45:00".into()),
+            parse_value(Some(&date_cell(45322.572916666664, "DATE_TIME")), &iso)
+        );
+        assert_eq!(
+            CellValue::Str("13:45:00".into()),
+            parse_value(Some(&date_cell(0.5729166666666666, "TIME")), &iso)
+        );
+        assert_eq!(
+            CellValue::Float(2460340.5),
+            parse_value(
+                Some(&date_cell(45322.0, "DATE")),
+                &options(DateTimeMode::JulianDay)
+            )
+        );
+        assert_eq!(
+            CellValue::Int(1706659200),
+            parse_value(
+                Some(&date_cell(45322.0, "DATE")),
+                &options(DateTimeMode::UnixEpoch)
+            )
+        );
+        assert_eq!(
+            CellValue::Float(45322.5),
+            parse_value(
+                Some(&date_cell(45322.5, "DATE_TIME")),
+                &options(DateTimeMode::Serial)
+            )
+        );
+        assert_eq!(
+            CellValue::Str("1/31/2024".into()),
+            parse_value(
+                Some(&date_cell(45322.0, "DATE")),
+                &options(DateTimeMode::Formatted)
+            )
+        );
+
+        // times of date-times, and negative serial numbers
+        assert_eq!(
+            CellValue::Str("13:45:00".into()),
+            parse_value(Some(&date_cell(45322.572916666664, "TIME")), &iso)
+        );
+        assert_eq!(
+            CellValue::Str("22:30:00".into()),
+            parse_value(Some(&date_cell(-0.0625, "TIME")), &iso)
+        );
+
+        // serial numbers out of the range of dates
+        assert_eq!(
+            CellValue::Int(150000000),
+            parse_value(Some(&number_cell(150000000.0, "150000000", None)), &iso)
+        );
+        assert_eq!(
+            CellValue::Str("1/31/2024".into()),
+            parse_value(Some(&date_cell(150000000.0, "DATE")), &iso)
+        );
+        assert_eq!(
+            CellValue::Str("1/31/2024".into()),
+            parse_value(
+                Some(&date_cell(1e300, "DATE_TIME")),
+                &options(DateTimeMode::UnixEpoch)
+            )
+        );
+    }
+
+    #[test]
+    fn test_to_user_entered() {
+        let options = ValueOptions::default();
+        let text = |s: &str| CellValue::Str(s.to_string());
+        let to_value =
+            |value: CellValue, cell: Option<&CellData>| to_user_entered(&value, cell, &options);
+
+        assert_eq!(json!(1234.0), to_value(text("1,234"), None));
+        assert_eq!(json!(-12.5), to_value(text("-$12.50"), None));
+        assert_eq!(json!(0.12), to_value(text("12%"), None));
+        assert_eq!(json!(true), to_value(text("true"), None));
+        assert_eq!(json!("1,23"), to_value(text("1,23"), None));
+        assert_eq!(json!("A00"), to_value(text("A00"), None));
+        assert_eq!(json!("=1+1"), to_value(text("=1+1"), None));
+        assert_eq!(json!(null), to_value(CellValue::Empty, None));
+
+        // text cells, and cells formatted as plain text, keep text
+        let text_cell = cell_data_from_json(&json!("A00"));
+        assert_eq!(json!("01234"), to_value(text("01234"), Some(&text_cell)));
+        assert_eq!(json!("true"), to_value(text("true"), Some(&text_cell)));
+        let plain_text = number_cell(1.0, "1", Some("TEXT"));
+        assert_eq!(json!("1,234"), to_value(text("1,234"), Some(&plain_text)));
+        assert_eq!(json!(1234.0), to_value(text("01234"), None));
+
+        let checkbox = cell_data_from_json(&json!(false));
+        assert_eq!(json!(true), to_value(CellValue::Int(1), Some(&checkbox)));
+        assert_eq!(json!(1), to_value(CellValue::Int(1), None));
+
+        let date = date_cell(45322.0, "DATE");
+        assert_eq!(json!(45323.0), to_value(text("2024-02-01"), Some(&date)));
+        assert_eq!(json!("2024-02-01"), to_value(text("2024-02-01"), None));
+        assert_eq!(
+            json!(45322.572916666664),
+            to_value(text("2024-01-31 13:45:00"), Some(&date))
+        );
+        assert_eq!(
+            json!(0.5729166666666666),
+            to_value(text("13:45"), Some(&date_cell(0.5, "TIME")))
+        );
+
+        let unix_epoch = ValueOptions {
+            date_time: DateTimeMode::UnixEpoch,
+            ..Default::default()
+        };
+        assert_eq!(
+            json!(45322.0),
+            to_user_entered(&CellValue::Int(1706659200), Some(&date), &unix_epoch)
+        );
+    }
+
+    #[test]
+    fn test_from_json() {
+        assert_eq!(
+            CellValue::Str("foo".into()),
+            CellValue::from_json(&json!("foo"))
+        );
+        assert_eq!(CellValue::Int(42), CellValue::from_json(&json!(42)));
+        assert_eq!(CellValue::Float(0.5), CellValue::from_json(&json!(0.5)));
+        assert_eq!(
+            CellValue::Str("TRUE".into()),
+            CellValue::from_json(&json!(true))
+        );
+        assert_eq!(CellValue::Empty, CellValue::from_json(&json!("")));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::sqlite3ext::sqlite3_api_routines;
+use google_sheets_api::error::Error as ApiError;
 use std::{
     ffi::CString,
     os::raw::{c_char, c_int},
@@ -16,8 +17,51 @@ pub enum SheetError {
     InvalidRange,
     #[error("Unknown option is provided")]
     UnknownOption,
-    #[error(transparent)]
+    #[error("Invalid value for {0}: {1}")]
+    InvalidValue(String, String),
+    #[error("The table is read-only. Create it with READONLY 'false' to write back to the sheet")]
+    ReadOnly,
+    #[error("Writing back to the sheet is not available in offline mode")]
+    Offline,
+    #[error("{0} can't be used along with READONLY 'false'")]
+    NotWritable(String),
+    #[error("{0} can't be used along with {1}")]
+    Incompatible(String, String),
+    #[error("{0} is not cached. Query it once while online")]
+    NotCached(String),
+    #[error("Environment variable {0} is not set")]
+    NoEnvironmentVariable(String),
+    #[error("INSERT is not supported")]
+    InsertNotSupported,
+    #[error("Changing rowid is not supported")]
+    RowidChange,
+    #[error("No row with rowid {0}")]
+    RowNotFound(usize),
+    #[error("{0} at {1}: {2}")]
+    CellError(String, String, String),
+    #[error("{0} has been changed by others since the table was created. Recreate the table and try again")]
+    Conflict(String),
+    #[error("{}", describe_api_error(.0))]
     Api(#[from] google_sheets_api::error::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+// Tells what to do about errors returned by Google, along with their messages.
+fn describe_api_error(err: &ApiError) -> String {
+    let hint = match err {
+        ApiError::Unauthenticated(_) => {
+            "Make sure you signed in with the account which has access to the spreadsheet"
+        }
+        ApiError::NotFound(_) => "Check ID and SHEET",
+        ApiError::PermissionDenied(_) => {
+            "Make sure the account you signed in with has access to the spreadsheet"
+        }
+        ApiError::RateLimited(_) => "Quota is exceeded, try again later",
+        ApiError::InvalidRange(_) => "Check SHEET and RANGE",
+        _ => return err.to_string(),
+    };
+    format!("{}. {}", err, hint)
 }
 
 impl From<SheetError> for String {
@@ -4,5 +4,8 @@ mod module; // entry point
 mod module_argument;
 mod range;
 mod reader;
+mod shadow_table;
 mod sheet;
 mod sqlite3ext;
+mod table_function;
+mod transaction;
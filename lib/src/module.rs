@@ -1,27 +1,47 @@
 use crate::{
-    cell_value::yield_cell_value,
-    error::error_to_sqlite3_string,
-    module_argument::collect_options_from_args,
+    cell_value::{yield_value, CellMode, CellValue},
+    error::{
+        error_to_sqlite3_string, SheetError,
+        SheetError::{
+            Api, Conflict, InsertNotSupported, InvalidValue, NoEnvironmentVariable, Offline,
+            ReadOnly, RowidChange,
+        },
+    },
+    module_argument::{collect_options_from_args, read_string_from_raw},
     reader::SheetReader,
+    shadow_table::{ShadowTable, SHADOW_TABLE_SUFFIX},
     sheet::Sheet,
     sqlite3ext::{
         sqlite3, sqlite3_api_routines, sqlite3_context, sqlite3_index_info, sqlite3_int64,
-        sqlite3_module, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_ERROR, SQLITE_OK,
-        SQLITE_OK_LOAD_PERMANENTLY,
+        sqlite3_module, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_AUTH, SQLITE_BUSY,
+        SQLITE_CONSTRAINT, SQLITE_ERROR, SQLITE_NULL, SQLITE_OK, SQLITE_OK_LOAD_PERMANENTLY,
+        SQLITE_PERM, SQLITE_READONLY,
     },
+    table_function::{find_argument_constraints, Arguments, FunctionColumn, TableFunction},
+    transaction::Transaction,
+};
+use google_sheets_api::{
+    cache::SpreadsheetCache,
+    client::{GoogleSheetsReadOnlyClient, CELL_FIELDS, DEFAULT_FIELDS, METADATA_FIELDS},
+    error::Error as ApiError,
+    HttpClient,
 };
-use google_sheets_api::client::GoogleSheetsReadOnlyClient;
 use std::{
     env,
     ffi::c_void,
-    ffi::CString,
+    ffi::{CStr, CString},
     os::raw::{c_char, c_int, c_longlong},
+    path::PathBuf,
     sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 #[no_mangle]
 static mut SQLITE3_API: *mut sqlite3_api_routines = std::ptr::null_mut();
 
+// HTTP client shared by all tables, to reuse connections to Google.
+static HTTP_CLIENT: Mutex<Option<HttpClient>> = Mutex::new(None);
+
 #[repr(C)]
 pub struct Module {
     // must be at the beginning
@@ -31,9 +51,11 @@ pub struct Module {
 
 const GSQLITE_MODULE: Module = Module {
     base: sqlite3_module {
-        iVersion: 0,
+        iVersion: 3,
+        // xCreate and xConnect have to be the same to make the module eponymous, i.e. available as
+        // a table-valued function
         xCreate: Some(gsqlite_create),
-        xConnect: Some(gsqlite_connect),
+        xConnect: Some(gsqlite_create),
         xBestIndex: Some(gsqlite_best_index),
         xDisconnect: Some(gsqlite_disconnect),
         xDestroy: Some(gsqlite_destroy),
@@ -44,6 +66,38 @@ const GSQLITE_MODULE: Module = Module {
         xEof: Some(gsqlite_eof),
         xColumn: Some(gsqlite_column),
         xRowid: Some(gsqlite_rowid),
+        xUpdate: Some(gsqlite_update),
+        xBegin: Some(gsqlite_begin),
+        xSync: Some(gsqlite_sync),
+        xCommit: Some(gsqlite_commit),
+        xRollback: Some(gsqlite_rollback),
+        xFindFunction: None,
+        xRename: Some(gsqlite_rename),
+        xSavepoint: Some(gsqlite_savepoint),
+        xRelease: Some(gsqlite_release),
+        xRollbackTo: Some(gsqlite_rollback_to),
+        xShadowName: Some(gsqlite_shadow_name),
+    },
+    name: b"gsqlite\0",
+};
+
+// `gsqlite_cells` is only available as a table-valued function, i.e. eponymous-only, as it has no
+// xCreate. It's read-only.
+const GSQLITE_CELLS_MODULE: Module = Module {
+    base: sqlite3_module {
+        iVersion: 3,
+        xCreate: None,
+        xConnect: Some(gsqlite_cells_connect),
+        xBestIndex: Some(gsqlite_best_index),
+        xDisconnect: Some(gsqlite_disconnect),
+        xDestroy: Some(gsqlite_disconnect),
+        xOpen: Some(gsqlite_open),
+        xClose: Some(gsqlite_close),
+        xFilter: Some(gsqlite_filter),
+        xNext: Some(gsqlite_next),
+        xEof: Some(gsqlite_eof),
+        xColumn: Some(gsqlite_column),
+        xRowid: Some(gsqlite_rowid),
         xUpdate: None,
         xBegin: None,
         xSync: None,
@@ -56,14 +110,19 @@ const GSQLITE_MODULE: Module = Module {
         xRollbackTo: None,
         xShadowName: None,
     },
-    name: b"gsqlite\0",
+    name: b"gsqlite_cells\0",
 };
 
 #[repr(C)]
 pub struct VirtualTable {
     // must be at the beginning
     pub base: sqlite3_vtab,
-    pub sheet: Arc<Mutex<Sheet>>,
+    // none for the table-valued function, which opens a sheet for each cursor
+    pub sheet: Option<Arc<Mutex<Sheet>>>,
+    pub transaction: Transaction,
+    pub shadow_table: Option<ShadowTable>,
+    // table-valued function which the table is, if it has no sheet
+    pub function: TableFunction,
 }
 
 #[repr(C)]
@@ -71,6 +130,8 @@ pub struct VirtualCursor {
     // must be at the beginning
     pub base: sqlite3_vtab_cursor,
     pub reader: Arc<Mutex<SheetReader>>,
+    // arguments given to the table-valued function
+    pub arguments: Option<Arguments>,
 }
 
 #[no_mangle]
@@ -79,12 +140,18 @@ unsafe extern "C" fn register_module(
     pz_err_msg: *mut *mut c_char,
     p_api: *mut sqlite3_api_routines,
 ) -> c_int {
-    let result = ((*p_api).create_module.unwrap())(
-        db,
-        GSQLITE_MODULE.name.as_ptr() as *const c_char,
-        &GSQLITE_MODULE as *const Module as *const sqlite3_module,
-        std::ptr::null_mut(),
-    );
+    let mut result = SQLITE_OK;
+    for module in [&GSQLITE_MODULE, &GSQLITE_CELLS_MODULE] {
+        result = ((*p_api).create_module.unwrap())(
+            db,
+            module.name.as_ptr() as *const c_char,
+            module as *const Module as *const sqlite3_module,
+            std::ptr::null_mut(),
+        );
+        if result != SQLITE_OK {
+            break;
+        }
+    }
 
     match result {
         SQLITE_OK => SQLITE_OK_LOAD_PERMANENTLY,
@@ -109,8 +176,11 @@ unsafe extern "C" fn sqlite3_gsqlite_init(
     let result = register_module(db, pz_err_msg, p_api);
     match result {
         SQLITE_OK => {
-            let result = ((*p_api).auto_extension.unwrap())(Some(std::mem::transmute(
-                register_module as *const (),
+            let result = ((*p_api).auto_extension.unwrap())(Some(std::mem::transmute::<
+                *const (),
+                unsafe extern "C" fn(),
+            >(
+                register_module as *const ()
             )));
             if result != SQLITE_OK {
                 return result;
@@ -131,104 +201,286 @@ unsafe extern "C" fn gsqlite_create(
     pp_vtab: *mut *mut sqlite3_vtab,
     pz_err: *mut *mut c_char,
 ) -> c_int {
-    let client_id = match env::var("LIBGSQLITE_GOOGLE_CLIENT_ID") {
-        Ok(v) => v,
-        Err(_) => panic!("Environment variable LIBGSQLITE_GOOGLE_CLIENT_ID is not set"),
-    };
-    let client_secret = match env::var("LIBGSQLITE_GOOGLE_CLIENT_SECRET") {
-        Ok(v) => v,
-        Err(_) => panic!("Environment variable LIBGSQLITE_GOOGLE_CLIENT_SECRET is not set"),
-    };
+    // the table-valued function is connected by the name of the module, without module arguments
+    if argc == 3 && read_string_from_raw(*argv.add(2)) == read_string_from_raw(*argv) {
+        return connect_function(db, TableFunction::Sheet, pp_vtab);
+    }
 
     match collect_options_from_args(argc, argv) {
-        Ok((id, sheet, range)) => {
+        Ok(options) => {
+            let shadow_table = ShadowTable::new(
+                db,
+                read_string_from_raw(*argv.add(1)),
+                read_string_from_raw(*argv.add(2)),
+            );
+            let offline = options.offline || is_offline();
+            let fields = if options.value_options.mode == CellMode::Json {
+                CELL_FIELDS
+            } else if options.metadata {
+                METADATA_FIELDS
+            } else {
+                DEFAULT_FIELDS
+            };
+            let read_only = options.read_only;
             let mut sheet = Sheet::builder()
-                .client(
-                    GoogleSheetsReadOnlyClient::builder()
-                        .client_id(client_id)
-                        .client_secret(client_secret)
-                        .cache_access_token(true)
-                        .build(),
-                )
-                .id(id)
-                .sheet(sheet)
-                .range(range)
+                .create_client(Box::new(move || create_client(read_only, offline, fields)))
+                .offline(offline)
+                .id(options.id)
+                .sheet(options.sheet)
+                .range(options.range)
+                .read_only(options.read_only)
+                .delete_mode(options.delete_mode)
+                .render(options.render)
+                .chunk_size(options.chunk_size)
+                .value_options(options.value_options)
+                .metadata(options.metadata)
+                .skip_empty_rows(options.skip_empty_rows)
                 .build();
 
-            match sheet.open() {
+            match open_sheet(&mut sheet, &shadow_table, options.ttl) {
                 Ok(_) => {
-                    let result = declare_table(db, SQLITE3_API, sheet.get_columns());
-                    let p_new = Box::new(VirtualTable {
-                        base: sqlite3_vtab {
-                            pModule: std::ptr::null_mut(),
-                            nRef: 0,
-                            zErrMsg: std::ptr::null_mut(),
-                        },
-                        sheet: Arc::new(Mutex::new(sheet)),
-                    });
+                    let result = declare_table(
+                        db,
+                        SQLITE3_API,
+                        sheet.get_columns(),
+                        sheet.get_hidden_columns(),
+                    );
+                    let p_new = new_virtual_table(Some(sheet), Some(shadow_table));
                     *pp_vtab = Box::into_raw(p_new) as *mut sqlite3_vtab;
                     result
                 }
-                Err(err) => {
-                    if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err) {
-                        *pz_err = ptr;
-                    }
-                    SQLITE_ERROR
-                }
+                Err(err) => set_create_error(pz_err, err),
             }
         }
-        Err(_) => SQLITE_ERROR,
+        Err(err) => set_create_error(pz_err, err),
     }
 }
 
 #[no_mangle]
-unsafe extern "C" fn gsqlite_connect(
+unsafe extern "C" fn gsqlite_cells_connect(
     db: *mut sqlite3,
-    p_aux: *mut c_void,
-    argc: c_int,
-    argv: *const *const c_char,
+    _p_aux: *mut c_void,
+    _argc: c_int,
+    _argv: *const *const c_char,
     pp_vtab: *mut *mut sqlite3_vtab,
-    pz_err: *mut *mut c_char,
+    _pz_err: *mut *mut c_char,
 ) -> c_int {
-    gsqlite_create(db, p_aux, argc, argv, pp_vtab, pz_err)
+    connect_function(db, TableFunction::Cells, pp_vtab)
+}
+
+unsafe fn connect_function(
+    db: *mut sqlite3,
+    function: TableFunction,
+    pp_vtab: *mut *mut sqlite3_vtab,
+) -> c_int {
+    let result = ((*SQLITE3_API).declare_vtab.unwrap())(
+        db,
+        function.create_declare_statement().as_ptr() as _,
+    );
+    let mut table = new_virtual_table(None, None);
+    table.function = function;
+    *pp_vtab = Box::into_raw(table) as *mut sqlite3_vtab;
+    result
+}
+
+unsafe fn set_create_error(pz_err: *mut *mut c_char, err: SheetError) -> c_int {
+    let code = get_error_code(&err);
+    if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err) {
+        *pz_err = ptr;
+    }
+    code
+}
+
+fn is_offline() -> bool {
+    env::var("LIBGSQLITE_OFFLINE").is_ok_and(|v| v == "1")
+}
+
+// Since xCreate and xConnect are the same, whether the table is being created or connected is told
+// by the shadow table. When connecting, loads a snapshot from it unless it's older than `ttl`
+// seconds, or LIBGSQLITE_REFRESH=1 is set. Otherwise fetches the sheet, and takes a snapshot.
+unsafe fn open_sheet(
+    sheet: &mut Sheet,
+    shadow_table: &ShadowTable,
+    ttl: Option<u64>,
+) -> Result<(), SheetError> {
+    // a snapshot of a chunked sheet would only have the first chunk
+    if sheet.is_chunked() {
+        return sheet.open();
+    }
+
+    let refresh = env::var("LIBGSQLITE_REFRESH").is_ok_and(|v| v == "1");
+    let snapshot = shadow_table.read(SQLITE3_API);
+    if let Some((created, data)) = snapshot.as_ref().filter(|_| !refresh) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if ttl.is_none_or(|ttl| now < created + ttl) {
+            return sheet.import(data);
+        }
+    }
+
+    match sheet.open() {
+        Ok(_) => {
+            // failing to take a snapshot, e.g. the database is read-only, only costs fetching next
+            // time
+            shadow_table.write(SQLITE3_API, &sheet.export());
+            Ok(())
+        }
+        // a stale snapshot is still better than failing to connect, e.g. without network, which
+        // would even prevent the table from being dropped
+        Err(err) => match snapshot {
+            Some((_, data)) => sheet.import(&data),
+            None => Err(err),
+        },
+    }
+}
+
+fn new_virtual_table(sheet: Option<Sheet>, shadow_table: Option<ShadowTable>) -> Box<VirtualTable> {
+    Box::new(VirtualTable {
+        base: sqlite3_vtab {
+            pModule: std::ptr::null_mut(),
+            nRef: 0,
+            zErrMsg: std::ptr::null_mut(),
+        },
+        sheet: sheet.map(|sheet| Arc::new(Mutex::new(sheet))),
+        transaction: Transaction::default(),
+        shadow_table,
+        function: TableFunction::Sheet,
+    })
+}
+
+// Credentials are not required in offline mode, as the client is never used. Fields of cells other
+// than values are fetched only if needed, as they make responses larger.
+fn create_client(
+    read_only: bool,
+    offline: bool,
+    fields: &str,
+) -> Result<GoogleSheetsReadOnlyClient, SheetError> {
+    let var = |name: &str| match env::var(name) {
+        Ok(v) => Ok(v),
+        Err(_) if offline => Ok("".to_string()),
+        Err(_) => Err(NoEnvironmentVariable(name.to_string())),
+    };
+    let seconds = |name: &str| match env::var(name) {
+        Ok(v) => v
+            .parse()
+            .map(|s| Some(Duration::from_secs(s)))
+            .map_err(|_| InvalidValue(name.to_string(), v)),
+        Err(_) => Ok(None),
+    };
+
+    let mut http_client = HTTP_CLIENT.lock().unwrap();
+    let client = GoogleSheetsReadOnlyClient::builder()
+        .client_id(var("LIBGSQLITE_GOOGLE_CLIENT_ID")?)
+        .client_secret(var("LIBGSQLITE_GOOGLE_CLIENT_SECRET")?)
+        .cache_access_token(true)
+        .read_only(read_only)
+        .cache(SpreadsheetCache::default())
+        .connect_timeout(seconds("LIBGSQLITE_CONNECT_TIMEOUT")?)
+        .timeout(seconds("LIBGSQLITE_TIMEOUT")?)
+        .proxy(env::var("LIBGSQLITE_PROXY").ok())
+        .root_certificates(env::var_os("LIBGSQLITE_ROOT_CERTIFICATES").map(PathBuf::from))
+        .http_client(http_client.clone())
+        .fields(fields)
+        .build();
+
+    if http_client.is_none() && !offline {
+        *http_client = Some(client.http_client()?);
+    }
+    Ok(client)
 }
 
 #[no_mangle]
 unsafe extern "C" fn gsqlite_best_index(
-    _p_vtab: *mut sqlite3_vtab,
-    _arg1: *mut sqlite3_index_info,
+    p_vtab: *mut sqlite3_vtab,
+    p_info: *mut sqlite3_index_info,
 ) -> c_int {
-    SQLITE_OK
+    let table = &*(p_vtab as *mut VirtualTable);
+    if table.sheet.is_some() {
+        return SQLITE_OK;
+    }
+
+    // the table-valued function requires all arguments to be given as equality constraints
+    let info = &mut *p_info;
+    if info.nConstraint == 0 {
+        return SQLITE_CONSTRAINT;
+    }
+    let constraints = std::slice::from_raw_parts(info.aConstraint, info.nConstraint as usize)
+        .iter()
+        .map(|c| (c.iColumn, c.op, c.usable != 0))
+        .collect::<Vec<_>>();
+
+    match find_argument_constraints(table.function, &constraints) {
+        Some(indices) => {
+            let usage =
+                std::slice::from_raw_parts_mut(info.aConstraintUsage, info.nConstraint as usize);
+            for (i, index) in indices.iter().enumerate() {
+                usage[*index].argvIndex = i as c_int + 1;
+                usage[*index].omit = 1;
+            }
+            SQLITE_OK
+        }
+        None => SQLITE_CONSTRAINT,
+    }
 }
 
 #[no_mangle]
 unsafe extern "C" fn gsqlite_disconnect(p_vtab: *mut sqlite3_vtab) -> c_int {
-    gsqlite_destroy(p_vtab)
+    if !p_vtab.is_null() {
+        let table = Box::from_raw(p_vtab as *mut VirtualTable);
+        drop(table);
+    }
+
+    SQLITE_OK
 }
 
 #[no_mangle]
 unsafe extern "C" fn gsqlite_destroy(p_vtab: *mut sqlite3_vtab) -> c_int {
     if !p_vtab.is_null() {
         let table = Box::from_raw(p_vtab as *mut VirtualTable);
+        if let Some(shadow_table) = &table.shadow_table {
+            let result = shadow_table.drop(SQLITE3_API);
+            if result != SQLITE_OK {
+                return result;
+            }
+        }
         drop(table);
     }
 
     SQLITE_OK
 }
 
+#[no_mangle]
+unsafe extern "C" fn gsqlite_rename(p_vtab: *mut sqlite3_vtab, z_new: *const c_char) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+
+    match &mut table.shadow_table {
+        Some(shadow_table) => shadow_table.rename(SQLITE3_API, &read_string_from_raw(z_new)),
+        None => SQLITE_OK,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn gsqlite_shadow_name(name: *const c_char) -> c_int {
+    (CStr::from_ptr(name).to_bytes() == SHADOW_TABLE_SUFFIX.as_bytes()) as c_int
+}
+
 #[no_mangle]
 unsafe extern "C" fn gsqlite_open(
     p_vtab: *mut sqlite3_vtab,
     pp_cursor: *mut *mut sqlite3_vtab_cursor,
 ) -> c_int {
     let table = &mut *(p_vtab as *mut VirtualTable);
-    let sheet = Arc::clone(&table.sheet);
-    let mut lock = sheet.lock().unwrap();
-    let reader = lock.get_reader();
+    let reader = match &table.sheet {
+        Some(sheet) => sheet.lock().unwrap().get_reader(),
+        None => SheetReader::new(Arc::default(), Arc::default()), // filled in xFilter
+    };
 
     let cursor = Box::new(VirtualCursor {
         base: sqlite3_vtab_cursor { pVtab: p_vtab },
         reader: Arc::new(Mutex::new(reader)),
+        arguments: None,
     });
     *pp_cursor = Box::into_raw(cursor) as _;
 
@@ -247,24 +499,83 @@ unsafe extern "C" fn gsqlite_close(p_cursor: *mut sqlite3_vtab_cursor) -> c_int
 
 #[no_mangle]
 unsafe extern "C" fn gsqlite_filter(
-    _arg1: *mut sqlite3_vtab_cursor,
+    p_cursor: *mut sqlite3_vtab_cursor,
     _idx_num: c_int,
     _idx_str: *const c_char,
-    _argc: c_int,
-    _argv: *mut *mut sqlite3_value,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
 ) -> c_int {
-    SQLITE_OK
+    let cursor = &mut *(p_cursor as *mut VirtualCursor);
+    let table = &*(cursor.base.pVtab as *mut VirtualTable);
+
+    let reader = match &table.sheet {
+        Some(sheet) => Ok(sheet.lock().unwrap().get_reader()),
+        None => {
+            let args = std::slice::from_raw_parts(argv, argc as usize)
+                .iter()
+                .map(|value| match CellValue::from_raw(SQLITE3_API, *value) {
+                    CellValue::Str(s) => s,
+                    _ => "".to_string(),
+                })
+                .collect::<Vec<_>>();
+
+            Arguments::new(&args[0], &args[1], &args[2]).and_then(|arguments| {
+                // formulas are fetched for cells
+                let fields = match table.function {
+                    TableFunction::Sheet => DEFAULT_FIELDS,
+                    TableFunction::Cells => METADATA_FIELDS,
+                };
+                let mut sheet = Sheet::builder()
+                    .create_client(Box::new(move || create_client(true, is_offline(), fields)))
+                    .offline(is_offline())
+                    .id(arguments.id.clone())
+                    .sheet(arguments.sheet.clone())
+                    .range(arguments.range.clone())
+                    .build();
+                sheet.open()?;
+                cursor.arguments = Some(arguments);
+                Ok(match table.function {
+                    TableFunction::Sheet => sheet.get_reader(),
+                    TableFunction::Cells => sheet.get_cells_reader(),
+                })
+            })
+        }
+    };
+
+    let result = reader.and_then(|mut reader| {
+        fetch_chunks(table, &mut reader)?;
+        *cursor.reader.lock().unwrap() = reader;
+        Ok(())
+    });
+    match result {
+        Ok(_) => SQLITE_OK,
+        Err(err) => set_error(cursor.base.pVtab, err),
+    }
 }
 
 #[no_mangle]
 unsafe extern "C" fn gsqlite_next(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
     let cursor = &mut *(p_cursor as *mut VirtualCursor);
+    let table = &*(cursor.base.pVtab as *mut VirtualTable);
     let lock = Arc::clone(&cursor.reader);
     let mut reader = lock.lock().unwrap();
 
     reader.move_next();
 
-    SQLITE_OK
+    match fetch_chunks(table, &mut reader) {
+        Ok(_) => SQLITE_OK,
+        Err(err) => set_error(cursor.base.pVtab, err),
+    }
+}
+
+// Fetches the next chunk once rows read so far are consumed, and the chunk after it as long as rows
+// of the chunk are all skipped.
+fn fetch_chunks(table: &VirtualTable, reader: &mut SheetReader) -> Result<(), SheetError> {
+    while let (Some(r1), Some(sheet)) = (reader.get_next_row(), &table.sheet) {
+        let (rows, next_row) = sheet.lock().unwrap().fetch_chunk(r1)?;
+        reader.push_chunk(r1, rows, next_row);
+    }
+    Ok(())
 }
 
 #[no_mangle]
@@ -287,12 +598,32 @@ unsafe extern "C" fn gsqlite_column(
     column: c_int,
 ) -> c_int {
     let cursor = &mut *(p_cursor as *mut VirtualCursor);
+    let table = &*(cursor.base.pVtab as *mut VirtualTable);
     let lock = Arc::clone(&cursor.reader);
     let reader = lock.lock().unwrap();
 
-    yield_cell_value(p_context, SQLITE3_API, reader.get_value(column as usize));
-
-    SQLITE_OK
+    match cursor
+        .arguments
+        .as_ref()
+        .map(|a| a.get_column(table.function, column as usize))
+    {
+        None => yield_value(
+            p_context,
+            SQLITE3_API,
+            reader
+                .get_value(column as usize)
+                .unwrap_or(&CellValue::Empty),
+        ),
+        Some(FunctionColumn::Cell(i)) => yield_value(
+            p_context,
+            SQLITE3_API,
+            reader.get_value(i).unwrap_or(&CellValue::Empty),
+        ),
+        Some(FunctionColumn::Argument(s)) => {
+            yield_value(p_context, SQLITE3_API, &CellValue::Str(s))
+        }
+        Some(FunctionColumn::Outside) => yield_value(p_context, SQLITE3_API, &CellValue::Empty),
+    }
 }
 
 #[no_mangle]
@@ -309,15 +640,163 @@ unsafe extern "C" fn gsqlite_rowid(
     SQLITE_OK
 }
 
+#[no_mangle]
+unsafe extern "C" fn gsqlite_update(
+    p_vtab: *mut sqlite3_vtab,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+    _p_rowid: *mut sqlite3_int64,
+) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    let sheet = match &table.sheet {
+        Some(sheet) => Arc::clone(sheet),
+        None => return set_error(p_vtab, ReadOnly),
+    };
+    let mut sheet = sheet.lock().unwrap();
+    let args = std::slice::from_raw_parts(argv, argc as usize);
+    let rowid = |value: *mut sqlite3_value| ((*SQLITE3_API).value_int64.unwrap())(value) as usize;
+
+    let result = if argc == 1 {
+        sheet.delete_row(rowid(args[0])).map(|change| vec![change])
+    } else if ((*SQLITE3_API).value_type.unwrap())(args[0]) == SQLITE_NULL {
+        Err(InsertNotSupported)
+    } else if rowid(args[0]) != rowid(args[1]) {
+        Err(RowidChange)
+    } else {
+        let values = args[2..]
+            .iter()
+            .map(|value| CellValue::from_raw(SQLITE3_API, *value))
+            .collect();
+        sheet.update_row(rowid(args[0]), values)
+    };
+
+    match result {
+        Ok(changes) => {
+            table.transaction.push(changes);
+            SQLITE_OK
+        }
+        Err(err) => set_error(p_vtab, err),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn gsqlite_begin(p_vtab: *mut sqlite3_vtab) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    if let Some(sheet) = &table.sheet {
+        table.transaction.begin(&sheet.lock().unwrap());
+    }
+
+    SQLITE_OK
+}
+
+// Changes are written in xSync rather than xCommit, since SQLite ignores errors from xCommit.
+#[no_mangle]
+unsafe extern "C" fn gsqlite_sync(p_vtab: *mut sqlite3_vtab) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    let result = match &table.sheet {
+        Some(sheet) => {
+            let mut sheet = sheet.lock().unwrap();
+            table.transaction.flush(&mut sheet).map(|_| {
+                if let Some(shadow_table) = &table.shadow_table {
+                    shadow_table.write(SQLITE3_API, &sheet.export());
+                }
+            })
+        }
+        None => Ok(()),
+    };
+
+    match result {
+        Ok(_) => SQLITE_OK,
+        Err(err) => set_error(p_vtab, err),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn gsqlite_commit(p_vtab: *mut sqlite3_vtab) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+
+    table.transaction.commit();
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn gsqlite_rollback(p_vtab: *mut sqlite3_vtab) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    if let Some(sheet) = &table.sheet {
+        table.transaction.rollback(&mut sheet.lock().unwrap());
+    }
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn gsqlite_savepoint(p_vtab: *mut sqlite3_vtab, level: c_int) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    if let Some(sheet) = &table.sheet {
+        table.transaction.savepoint(level, &sheet.lock().unwrap());
+    }
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn gsqlite_release(p_vtab: *mut sqlite3_vtab, level: c_int) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+
+    table.transaction.release(level);
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn gsqlite_rollback_to(p_vtab: *mut sqlite3_vtab, level: c_int) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    if let Some(sheet) = &table.sheet {
+        table
+            .transaction
+            .rollback_to(level, &mut sheet.lock().unwrap());
+    }
+
+    SQLITE_OK
+}
+
+unsafe fn set_error(p_vtab: *mut sqlite3_vtab, err: SheetError) -> c_int {
+    let code = get_error_code(&err);
+    if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err) {
+        (*p_vtab).zErrMsg = ptr;
+    }
+    code
+}
+
+fn get_error_code(err: &SheetError) -> c_int {
+    match err {
+        ReadOnly | Offline => SQLITE_READONLY,
+        Conflict(_) | Api(ApiError::RateLimited(_)) => SQLITE_BUSY,
+        Api(ApiError::Unauthenticated(_)) => SQLITE_AUTH,
+        Api(ApiError::PermissionDenied(_)) => SQLITE_PERM,
+        _ => SQLITE_ERROR,
+    }
+}
+
 unsafe fn declare_table(
     db: *mut sqlite3,
     api: *mut sqlite3_api_routines,
     columns: Vec<String>,
+    hidden_columns: Vec<String>,
 ) -> c_int {
-    ((*api).declare_vtab.unwrap())(db, create_declare_table_statement(columns).as_ptr() as _)
+    ((*api).declare_vtab.unwrap())(
+        db,
+        create_declare_table_statement(columns, hidden_columns).as_ptr() as _,
+    )
 }
 
-fn create_declare_table_statement(columns: Vec<String>) -> CString {
+// Hidden columns are left out of `SELECT *`, but can be selected by their names.
+fn create_declare_table_statement(columns: Vec<String>, hidden_columns: Vec<String>) -> CString {
+    let columns = columns
+        .into_iter()
+        .chain(hidden_columns.into_iter().map(|c| format!("{} HIDDEN", c)))
+        .collect::<Vec<_>>();
     CString::new(format!("CREATE TABLE sheet({})", columns.join(", "))).unwrap()
 }
 
@@ -356,6 +835,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_create_without_arguments() -> Result<(), Box<dyn Error>> {
+        let conn = Connection::open_in_memory()?;
+        load_my_extension(&conn)?;
+
+        let result = conn.execute("CREATE VIRTUAL TABLE t USING gsqlite;", ());
+        assert!(result.is_err_and(|err| err.to_string().contains("No ID is provided")));
+        Ok(())
+    }
+
     #[test]
     fn test_extension() -> Result<(), Box<dyn Error>> {
         let conn = Connection::open_in_memory()?;
@@ -426,8 +915,13 @@ mod tests {
                 vec!["A", "B", "C"]
                     .into_iter()
                     .map(|s| s.to_string())
-                    .collect()
+                    .collect(),
+                Vec::new()
             )
-        )
+        );
+        assert_eq!(
+            CString::new("CREATE TABLE sheet(A, A_formula HIDDEN)").unwrap(),
+            create_declare_table_statement(vec!["A".to_string()], vec!["A_formula".to_string()])
+        );
     }
 }
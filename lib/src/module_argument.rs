@@ -1,8 +1,13 @@
 use crate::{
+    cell_value::{BooleanMode, CellMode, DateTimeMode, ErrorMode, ValueOptions},
     error::SheetError,
-    error::SheetError::{InvalidRange, NoId, NoSheet, UnknownOption},
+    error::SheetError::{
+        Incompatible, InvalidRange, InvalidValue, NoId, NoSheet, NotWritable, UnknownOption,
+    },
     range::Range,
+    sheet::DeleteMode,
 };
+use google_sheets_api::value_range::ValueRenderOption;
 use regex::Regex;
 use std::{
     ffi::CStr,
@@ -13,42 +18,119 @@ enum ModuleArgument {
     Id(String),
     Sheet(String),
     Range(Range),
+    ReadOnly(bool),
+    DeleteMode(DeleteMode),
+    Ttl(u64),
+    Offline(bool),
+    Render(ValueRenderOption),
+    ChunkSize(usize),
+    DateTime(DateTimeMode),
+    Boolean(BooleanMode),
+    Errors(ErrorMode),
+    Metadata(bool),
+    Mode(CellMode),
+    Trim(bool),
+    EmptyAsNull(bool),
+    SkipEmptyRows(bool),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ModuleOptions {
+    pub id: String,
+    pub sheet: String,
+    pub range: Range,
+    pub read_only: bool,
+    pub delete_mode: DeleteMode,
+    pub ttl: Option<u64>,
+    pub offline: bool,
+    // fetches values only, rendered as specified, rather than grid data
+    pub render: Option<ValueRenderOption>,
+    // number of rows to fetch at once, fetching the next ones as they are read
+    pub chunk_size: Option<usize>,
+    pub value_options: ValueOptions,
+    // exposes metadata of cells as hidden columns
+    pub metadata: bool,
+    // skips rows whose cells are all empty
+    pub skip_empty_rows: bool,
 }
 
 pub unsafe fn collect_options_from_args(
     argc: c_int,
     argv: *const *const c_char,
-) -> Result<(String, String, Range), SheetError> {
-    let mut id = "".to_string();
-    let mut sheet = "".to_string();
-    let mut range = Range {
-        c1: "A".to_string(),
-        r1: 0,
-        c2: "A".to_string(),
-        r2: 0,
+) -> Result<ModuleOptions, SheetError> {
+    let mut options = ModuleOptions {
+        id: "".to_string(),
+        sheet: "".to_string(),
+        range: Range {
+            c1: "A".to_string(),
+            r1: 0,
+            c2: "A".to_string(),
+            r2: 0,
+        },
+        read_only: true,
+        delete_mode: DeleteMode::Delete,
+        ttl: None,
+        offline: false,
+        render: None,
+        chunk_size: None,
+        value_options: ValueOptions::default(),
+        metadata: false,
+        skip_empty_rows: false,
     };
 
     for arg in collect_strings_from_raw(argc as usize, argv) {
-        if let Ok(option) = parse_option(arg.as_str()) {
-            match option {
-                ModuleArgument::Id(i) => id = i.to_string(),
-                ModuleArgument::Sheet(s) => sheet = s.to_string(),
-                ModuleArgument::Range(r) => range = r,
-            }
+        match parse_option(arg.as_str()) {
+            Ok(ModuleArgument::Id(i)) => options.id = i.to_string(),
+            Ok(ModuleArgument::Sheet(s)) => options.sheet = s.to_string(),
+            Ok(ModuleArgument::Range(r)) => options.range = r,
+            Ok(ModuleArgument::ReadOnly(b)) => options.read_only = b,
+            Ok(ModuleArgument::DeleteMode(m)) => options.delete_mode = m,
+            Ok(ModuleArgument::Ttl(t)) => options.ttl = Some(t),
+            Ok(ModuleArgument::Offline(b)) => options.offline = b,
+            Ok(ModuleArgument::Render(r)) => options.render = Some(r),
+            Ok(ModuleArgument::ChunkSize(n)) => options.chunk_size = Some(n),
+            Ok(ModuleArgument::DateTime(m)) => options.value_options.date_time = m,
+            Ok(ModuleArgument::Boolean(m)) => options.value_options.boolean = m,
+            Ok(ModuleArgument::Errors(m)) => options.value_options.errors = m,
+            Ok(ModuleArgument::Metadata(b)) => options.metadata = b,
+            Ok(ModuleArgument::Mode(m)) => options.value_options.mode = m,
+            Ok(ModuleArgument::Trim(b)) => options.value_options.trim = b,
+            Ok(ModuleArgument::EmptyAsNull(b)) => options.value_options.empty_as_null = b,
+            Ok(ModuleArgument::SkipEmptyRows(b)) => options.skip_empty_rows = b,
+            Err(InvalidValue(option, value)) => return Err(InvalidValue(option, value)),
+            Err(_) => {}
         }
     }
 
-    if id.is_empty() {
+    if options.id.is_empty() {
         return Err(NoId);
     }
-    if sheet.is_empty() {
+    if options.sheet.is_empty() {
         return Err(NoSheet);
     }
-    if range.r1 == 0 || range.r2 == 0 {
+    if options.range.r1 == 0 || options.range.r2 == 0 {
         return Err(InvalidRange);
     }
+    // metadata are only available in grid data
+    if options.metadata && options.render.is_some() {
+        return Err(Incompatible(
+            "METADATA 'true'".to_string(),
+            "RENDER".to_string(),
+        ));
+    }
+    if !options.read_only {
+        if options.render.is_some() {
+            return Err(NotWritable("RENDER".to_string()));
+        }
+        if options.chunk_size.is_some() {
+            return Err(NotWritable("CHUNK_SIZE".to_string()));
+        }
+        if options.value_options.mode == CellMode::Json {
+            return Err(NotWritable("MODE 'json'".to_string()));
+        }
+    }
 
-    Ok((id, sheet, range))
+    Ok(options)
 }
 
 unsafe fn collect_strings_from_raw(n: usize, args: *const *const c_char) -> Vec<String> {
@@ -64,18 +146,73 @@ unsafe fn collect_strings_from_raw(n: usize, args: *const *const c_char) -> Vec<
     vec
 }
 
-unsafe fn read_string_from_raw(raw: *const c_char) -> String {
+pub unsafe fn read_string_from_raw(raw: *const c_char) -> String {
     let cstr = CStr::from_ptr(raw);
     cstr.to_str().unwrap_or_default().to_string()
 }
 
 fn parse_option(input: &str) -> Result<ModuleArgument, SheetError> {
-    if let Ok(re) = Regex::new(r#"(?i)^(ID|SHEET|RANGE)\s+['"]([^'"]+)['"]$"#) {
+    if let Ok(re) = Regex::new(
+        r#"(?i)^(ID|SHEET|RANGE|READONLY|DELETE_MODE|TTL|OFFLINE|RENDER|CHUNK_SIZE|DATETIME|BOOLEAN|ERRORS|METADATA|MODE|TRIM|EMPTY_AS_NULL|SKIP_EMPTY_ROWS)\s+(?:['"]([^'"]+)['"]|([^'"\s]+))$"#,
+    ) {
         if let Some(cap) = re.captures(input) {
+            // values may be unquoted, e.g. `TRIM true`
+            let value = cap.get(2).or_else(|| cap.get(3)).map_or("", |m| m.as_str());
             return match cap[1].to_lowercase().as_str() {
-                "id" => Ok(ModuleArgument::Id(cap[2].into())),
-                "sheet" => Ok(ModuleArgument::Sheet(cap[2].into())),
-                "range" => Ok(ModuleArgument::Range(cap[2].into())),
+                "id" => Ok(ModuleArgument::Id(value.into())),
+                "sheet" => Ok(ModuleArgument::Sheet(value.into())),
+                "range" => Ok(ModuleArgument::Range(value.into())),
+                "readonly" => parse_bool(&cap[1], value).map(ModuleArgument::ReadOnly),
+                "offline" => parse_bool(&cap[1], value).map(ModuleArgument::Offline),
+                "metadata" => parse_bool(&cap[1], value).map(ModuleArgument::Metadata),
+                "trim" => parse_bool(&cap[1], value).map(ModuleArgument::Trim),
+                "empty_as_null" => parse_bool(&cap[1], value).map(ModuleArgument::EmptyAsNull),
+                "skip_empty_rows" => parse_bool(&cap[1], value).map(ModuleArgument::SkipEmptyRows),
+                "delete_mode" => match value.to_lowercase().as_str() {
+                    "delete" => Ok(ModuleArgument::DeleteMode(DeleteMode::Delete)),
+                    "clear" => Ok(ModuleArgument::DeleteMode(DeleteMode::Clear)),
+                    _ => Err(InvalidValue(cap[1].into(), value.into())),
+                },
+                "render" => match value.to_lowercase().as_str() {
+                    "formatted" => Ok(ModuleArgument::Render(ValueRenderOption::FormattedValue)),
+                    "unformatted" => {
+                        Ok(ModuleArgument::Render(ValueRenderOption::UnformattedValue))
+                    }
+                    "formula" => Ok(ModuleArgument::Render(ValueRenderOption::Formula)),
+                    _ => Err(InvalidValue(cap[1].into(), value.into())),
+                },
+                "chunk_size" => match value.parse() {
+                    Ok(n) if n > 0 => Ok(ModuleArgument::ChunkSize(n)),
+                    _ => Err(InvalidValue(cap[1].into(), value.into())),
+                },
+                "datetime" => match value.to_lowercase().as_str() {
+                    "iso8601" => Ok(ModuleArgument::DateTime(DateTimeMode::Iso8601)),
+                    "julianday" => Ok(ModuleArgument::DateTime(DateTimeMode::JulianDay)),
+                    "unixepoch" => Ok(ModuleArgument::DateTime(DateTimeMode::UnixEpoch)),
+                    "serial" => Ok(ModuleArgument::DateTime(DateTimeMode::Serial)),
+                    "formatted" => Ok(ModuleArgument::DateTime(DateTimeMode::Formatted)),
+                    _ => Err(InvalidValue(cap[1].into(), value.into())),
+                },
+                "boolean" => match value.to_lowercase().as_str() {
+                    "integer" => Ok(ModuleArgument::Boolean(BooleanMode::Integer)),
+                    "text" => Ok(ModuleArgument::Boolean(BooleanMode::Text)),
+                    _ => Err(InvalidValue(cap[1].into(), value.into())),
+                },
+                "mode" => match value.to_lowercase().as_str() {
+                    "value" => Ok(ModuleArgument::Mode(CellMode::Value)),
+                    "json" => Ok(ModuleArgument::Mode(CellMode::Json)),
+                    _ => Err(InvalidValue(cap[1].into(), value.into())),
+                },
+                "errors" => match value.to_lowercase().as_str() {
+                    "text" => Ok(ModuleArgument::Errors(ErrorMode::Text)),
+                    "null" => Ok(ModuleArgument::Errors(ErrorMode::Null)),
+                    "fail" => Ok(ModuleArgument::Errors(ErrorMode::Fail)),
+                    _ => Err(InvalidValue(cap[1].into(), value.into())),
+                },
+                "ttl" => value
+                    .parse()
+                    .map(ModuleArgument::Ttl)
+                    .map_err(|_| InvalidValue(cap[1].into(), value.into())),
                 _ => Err(UnknownOption),
             };
         }
@@ -84,12 +221,27 @@ fn parse_option(input: &str) -> Result<ModuleArgument, SheetError> {
     Err(UnknownOption)
 }
 
+fn parse_bool(option: &str, value: &str) -> Result<bool, SheetError> {
+    match value.to_lowercase().as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(InvalidValue(option.into(), value.into())),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::module_argument::collect_options_from_args;
+    use crate::{
+        cell_value::{BooleanMode, CellMode, DateTimeMode, ErrorMode, ValueOptions},
+        error::SheetError::{Incompatible, InvalidValue, NotWritable},
+        module_argument::{collect_options_from_args, ModuleOptions},
+        sheet::DeleteMode,
+    };
+    use google_sheets_api::value_range::ValueRenderOption;
     use std::ffi::CStr;
 
     #[test]
+    #[allow(clippy::manual_c_str_literals)]
     fn test_collect_options_from_args() {
         unsafe {
             let mut v = Vec::with_capacity(4);
@@ -100,13 +252,109 @@ mod tests {
             let out = v.into_iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
 
             assert_eq!(
-                (
-                    "some_random_id".to_string(),
-                    "JP".to_string(),
-                    "A2:F5".into()
-                ),
+                ModuleOptions {
+                    id: "some_random_id".to_string(),
+                    sheet: "JP".to_string(),
+                    range: "A2:F5".into(),
+                    read_only: true,
+                    delete_mode: DeleteMode::Delete,
+                    ttl: None,
+                    offline: false,
+                    render: None,
+                    chunk_size: None,
+                    value_options: ValueOptions::default(),
+                    metadata: false,
+                    skip_empty_rows: false,
+                },
                 collect_options_from_args(3, out.as_ptr()).unwrap()
             )
         }
     }
+
+    #[test]
+    fn test_collect_optional_options_from_args() {
+        unsafe {
+            let v = [
+                c"ID 'some_random_id'",
+                c"SHEET 'JP'",
+                c"RANGE 'A2:F5'",
+                c"READONLY 'false'",
+                c"DELETE_MODE 'clear'",
+                c"TTL '3600'",
+                c"OFFLINE 'TRUE'",
+                c"METADATA 'true'",
+            ];
+            let out = v.into_iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+            let options = collect_options_from_args(8, out.as_ptr()).unwrap();
+            assert!(options.metadata);
+            assert!(options.offline);
+            assert!(!options.read_only);
+            assert_eq!(DeleteMode::Clear, options.delete_mode);
+            assert_eq!(Some(3600), options.ttl);
+
+            let v = [
+                c"ID 'some_random_id'",
+                c"SHEET 'JP'",
+                c"RANGE 'A2:F5'",
+                c"RENDER 'Formula'",
+                c"CHUNK_SIZE '1000'",
+                c"DATETIME 'unixepoch'",
+                c"BOOLEAN 'text'",
+                c"ERRORS 'null'",
+                c"MODE 'json'",
+                c"TRIM true",
+                c"EMPTY_AS_NULL 'TRUE'",
+                c"SKIP_EMPTY_ROWS true",
+            ];
+            let out = v.into_iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+            let options = collect_options_from_args(12, out.as_ptr()).unwrap();
+            assert!(options.value_options.trim);
+            assert!(options.value_options.empty_as_null);
+            assert!(options.skip_empty_rows);
+            assert_eq!(CellMode::Json, options.value_options.mode);
+            assert_eq!(ErrorMode::Null, options.value_options.errors);
+            assert_eq!(BooleanMode::Text, options.value_options.boolean);
+            assert_eq!(DateTimeMode::UnixEpoch, options.value_options.date_time);
+            assert_eq!(Some(ValueRenderOption::Formula), options.render);
+            assert_eq!(Some(1000), options.chunk_size);
+
+            let v = [
+                c"ID 'some_random_id'",
+                c"SHEET 'JP'",
+                c"RANGE 'A2:F5'",
+                c"RENDER 'formula'",
+                c"READONLY 'false'",
+            ];
+            let out = v.into_iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+            assert!(matches!(
+                collect_options_from_args(5, out.as_ptr()),
+                Err(NotWritable(_))
+            ));
+
+            let v = [
+                c"ID 'some_random_id'",
+                c"SHEET 'JP'",
+                c"RANGE 'A2:F5'",
+                c"RENDER 'unformatted'",
+                c"METADATA 'true'",
+            ];
+            let out = v.into_iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+            assert!(matches!(
+                collect_options_from_args(5, out.as_ptr()),
+                Err(Incompatible(_, _))
+            ));
+
+            let v = [c"ID 'some_random_id'", c"DELETE_MODE 'shift'"];
+            let out = v.into_iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+            assert!(matches!(
+                collect_options_from_args(2, out.as_ptr()),
+                Err(InvalidValue(_, _))
+            ));
+        }
+    }
 }
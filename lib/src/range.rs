@@ -1,6 +1,7 @@
 use regex::Regex;
+use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Range {
     pub c1: String,
     pub r1: usize,
@@ -12,12 +13,20 @@ impl From<&str> for Range {
     fn from(s: &str) -> Self {
         if let Ok(re) = Regex::new(r#"(?i)^([a-z]+)(\d+):([a-z]+)(\d+)$"#) {
             if let Some(cap) = re.captures(s) {
-                return Range {
-                    c1: cap[1].to_string().to_uppercase(),
-                    r1: cap[2].parse::<usize>().unwrap_or_default(), // default should be invalid as a range
-                    c2: cap[3].to_string().to_uppercase(),
-                    r2: cap[4].parse::<usize>().unwrap_or_default(), // default should be invalid as a range
-                };
+                let mut c1 = cap[1].to_string().to_uppercase();
+                let mut r1 = cap[2].parse::<usize>().unwrap_or_default(); // default should be invalid as a range
+                let mut c2 = cap[3].to_string().to_uppercase();
+                let mut r2 = cap[4].parse::<usize>().unwrap_or_default(); // default should be invalid as a range
+
+                // reversed ranges, e.g. `D5:A2`, are accepted by Google as the same as `A2:D5`, hence
+                // corners are normalized so that the first one is always top left
+                if (c1.len(), &c1) > (c2.len(), &c2) {
+                    std::mem::swap(&mut c1, &mut c2);
+                }
+                if r1 > r2 {
+                    std::mem::swap(&mut r1, &mut r2);
+                }
+                return Range { c1, r1, c2, r2 };
             }
         }
 
@@ -31,9 +40,9 @@ impl From<&str> for Range {
     }
 }
 
-impl ToString for Range {
-    fn to_string(&self) -> String {
-        format!("{}{}:{}{}", self.c1, self.r1, self.c2, self.r2)
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}:{}{}", self.c1, self.r1, self.c2, self.r2)
     }
 }
 
@@ -42,3 +51,17 @@ impl From<&Range> for String {
         r.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::range::Range;
+
+    #[test]
+    fn test_from() {
+        assert_eq!("A2:D5", Range::from("a2:d5").to_string());
+        assert_eq!("A2:D5", Range::from("D5:A2").to_string());
+        assert_eq!("A2:D5", Range::from("D2:A5").to_string());
+        assert_eq!("Z2:AA5", Range::from("AA2:Z5").to_string());
+        assert_eq!(0, Range::from("A2").r1);
+    }
+}
@@ -1,37 +1,112 @@
-use google_sheets_api::{CellData, RowData};
+use crate::cell_value::CellValue;
+use std::sync::Arc;
 
+/// Rows shared between a sheet and readers.
+pub type SharedRows = Arc<Vec<Vec<CellValue>>>;
+
+/// A cursor over rows, which are shared with the sheet and other readers rather than copied.
 pub struct SheetReader {
-    rows: Vec<RowData>,
+    rows: Arc<Vec<Vec<CellValue>>>,
+    row_ids: Arc<Vec<usize>>,
     current_row_id: usize,
+    // row number of the chunk to be fetched once the rows are consumed, if any
+    next_row: Option<usize>,
+    // whether rows whose values are all empty are skipped
+    skip_empty_rows: bool,
 }
 
 impl SheetReader {
-    pub fn new(rows: Vec<RowData>) -> Self {
+    pub fn new(rows: Arc<Vec<Vec<CellValue>>>, row_ids: Arc<Vec<usize>>) -> Self {
         SheetReader {
             rows,
+            row_ids,
             current_row_id: 0,
+            next_row: None,
+            skip_empty_rows: false,
         }
     }
 
+    pub fn with_skip_empty_rows(mut self, skip_empty_rows: bool) -> Self {
+        self.skip_empty_rows = skip_empty_rows;
+        self.skip();
+        self
+    }
+
+    pub fn with_next_row(mut self, next_row: Option<usize>) -> Self {
+        self.next_row = next_row;
+        self
+    }
+
+    /// Returns the row number of the chunk to be fetched, once all rows read so far are consumed.
+    pub fn get_next_row(&self) -> Option<usize> {
+        self.next_row.filter(|_| !self.has_value())
+    }
+
+    /// Replaces the rows with a chunk starting at row `r1`, so that only a chunk is kept at a time.
+    pub fn push_chunk(&mut self, r1: usize, rows: SharedRows, next_row: Option<usize>) {
+        self.row_ids = Arc::new((r1..r1 + rows.len()).collect());
+        self.rows = rows;
+        self.current_row_id = 0;
+        self.next_row = next_row;
+        self.skip();
+    }
+
     pub fn get_rowid(&self) -> u32 {
-        self.current_row_id as u32
+        self.row_ids
+            .get(self.current_row_id)
+            .copied()
+            .unwrap_or_default() as u32
     }
 
     pub fn move_next(&mut self) {
         self.current_row_id += 1;
+        self.skip();
+    }
+
+    // Moves past empty rows, if they are skipped.
+    fn skip(&mut self) {
+        while self.skip_empty_rows
+            && self
+                .rows
+                .get(self.current_row_id)
+                .is_some_and(|row| row.iter().all(|v| v == &CellValue::Empty))
+        {
+            self.current_row_id += 1;
+        }
     }
 
     pub fn has_value(&self) -> bool {
         self.rows.get(self.current_row_id).is_some()
     }
 
-    pub fn get_value(&self, i: usize) -> Option<&CellData> {
-        if let Some(row) = self.rows.get(self.current_row_id) {
-            if let Some(cell_data) = row.values.as_ref() {
-                return cell_data.get(i);
-            }
-        }
+    pub fn get_value(&self, i: usize) -> Option<&CellValue> {
+        self.rows
+            .get(self.current_row_id)
+            .and_then(|row| row.get(i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cell_value::CellValue, reader::SheetReader};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_skip_empty_rows() {
+        let row =
+            |v: Option<i64>| vec![CellValue::Empty, v.map_or(CellValue::Empty, CellValue::Int)];
+        let rows = vec![row(None), row(Some(1)), Vec::new(), row(None), row(Some(2))];
+        let mut reader =
+            SheetReader::new(Arc::new(rows), Arc::new((2..7).collect())).with_skip_empty_rows(true);
+
+        assert_eq!(3, reader.get_rowid());
+        reader.move_next();
+        assert_eq!(6, reader.get_rowid());
+        assert_eq!(Some(&CellValue::Int(2)), reader.get_value(1));
+        reader.move_next();
+        assert!(!reader.has_value());
 
-        None
+        reader.push_chunk(7, Arc::new(vec![row(None), row(Some(3))]), None);
+        assert_eq!(8, reader.get_rowid());
     }
 }
@@ -0,0 +1,165 @@
+use crate::sqlite3ext::{sqlite3, sqlite3_api_routines, sqlite3_stmt, SQLITE_OK, SQLITE_ROW};
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Suffix of the shadow table, `<table>_cache`, which keeps a snapshot of the sheet.
+pub const SHADOW_TABLE_SUFFIX: &str = "cache";
+
+/// A shadow table to persist a snapshot of a sheet into the database, so that reconnecting to the
+/// database doesn't fetch the sheet again.
+pub struct ShadowTable {
+    db: *mut sqlite3,
+    schema: String,
+    name: String,
+}
+
+impl ShadowTable {
+    pub fn new(db: *mut sqlite3, schema: impl Into<String>, name: impl Into<String>) -> Self {
+        ShadowTable {
+            db,
+            schema: schema.into(),
+            name: name.into(),
+        }
+    }
+
+    /// Returns a snapshot, and when it was taken in seconds since Unix epoch, if any.
+    pub unsafe fn read(&self, api: *mut sqlite3_api_routines) -> Option<(u64, String)> {
+        let sql = format!("SELECT value FROM {} WHERE key = ?", self.table_name());
+
+        let created = self.query(api, &sql, &["created"])?.parse().ok()?;
+        let data = self.query(api, &sql, &["data"])?;
+        Some((created, data))
+    }
+
+    pub unsafe fn write(&self, api: *mut sqlite3_api_routines, data: &str) -> c_int {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {}(key TEXT PRIMARY KEY, value) WITHOUT ROWID",
+            self.table_name()
+        );
+        let result = self.execute(api, &sql, &[]);
+        if result != SQLITE_OK {
+            return result;
+        }
+
+        let sql = format!(
+            "INSERT OR REPLACE INTO {}(key, value) VALUES ('created', ?), ('data', ?)",
+            self.table_name()
+        );
+        self.execute(api, &sql, &[&created, data])
+    }
+
+    pub unsafe fn drop(&self, api: *mut sqlite3_api_routines) -> c_int {
+        let sql = format!("DROP TABLE IF EXISTS {}", self.table_name());
+        self.execute(api, &sql, &[])
+    }
+
+    pub unsafe fn rename(&mut self, api: *mut sqlite3_api_routines, name: &str) -> c_int {
+        let sql = format!(
+            "ALTER TABLE {} RENAME TO {}",
+            self.table_name(),
+            quote(&format!("{}_{}", name, SHADOW_TABLE_SUFFIX))
+        );
+        let result = self.execute(api, &sql, &[]);
+        if result == SQLITE_OK {
+            self.name = name.to_string();
+        }
+        result
+    }
+
+    // Returns the first column of the first row, if any.
+    unsafe fn query(
+        &self,
+        api: *mut sqlite3_api_routines,
+        sql: &str,
+        params: &[&str],
+    ) -> Option<String> {
+        let (stmt, _params) = self.prepare(api, sql, params)?;
+
+        let mut value = None;
+        if ((*api).step.unwrap())(stmt) == SQLITE_ROW {
+            let raw = ((*api).column_text.unwrap())(stmt, 0);
+            if !raw.is_null() {
+                value = Some(
+                    CStr::from_ptr(raw as *const c_char)
+                        .to_string_lossy()
+                        .to_string(),
+                );
+            }
+        }
+        ((*api).finalize.unwrap())(stmt);
+        value
+    }
+
+    unsafe fn execute(&self, api: *mut sqlite3_api_routines, sql: &str, params: &[&str]) -> c_int {
+        match self.prepare(api, sql, params) {
+            Some((stmt, _params)) => {
+                ((*api).step.unwrap())(stmt);
+                ((*api).finalize.unwrap())(stmt)
+            }
+            None => ((*api).errcode.unwrap())(self.db),
+        }
+    }
+
+    // Prepares a statement with parameters bound. Parameters are kept alive along with the
+    // statement, as they are bound without copying.
+    unsafe fn prepare(
+        &self,
+        api: *mut sqlite3_api_routines,
+        sql: &str,
+        params: &[&str],
+    ) -> Option<(*mut sqlite3_stmt, Vec<CString>)> {
+        let sql = CString::new(sql).ok()?;
+        let mut stmt = std::ptr::null_mut();
+        let result = ((*api).prepare_v2.unwrap())(
+            self.db,
+            sql.as_ptr(),
+            -1,
+            &mut stmt,
+            std::ptr::null_mut(),
+        );
+        if result != SQLITE_OK {
+            return None;
+        }
+
+        let params = params
+            .iter()
+            .map(|p| CString::new(*p).unwrap_or_default())
+            .collect::<Vec<_>>();
+        for (i, param) in params.iter().enumerate() {
+            ((*api).bind_text.unwrap())(stmt, i as c_int + 1, param.as_ptr(), -1, None);
+        }
+        Some((stmt, params))
+    }
+
+    fn table_name(&self) -> String {
+        format!(
+            "{}.{}",
+            quote(&self.schema),
+            quote(&format!("{}_{}", self.name, SHADOW_TABLE_SUFFIX))
+        )
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shadow_table::quote;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!("\"main\"", quote("main"));
+        assert_eq!("\"my \"\"table\"\"\"", quote("my \"table\""));
+    }
+}
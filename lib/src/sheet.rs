@@ -1,70 +1,595 @@
 use crate::{
-    error::{SheetError, SheetError::Api},
+    cell_value::{
+        cell_data_from_json, get_value_type, parse_metadata, parse_value, to_user_entered,
+        CellValue, DateTimeMode, ValueOptions, METADATA_SUFFIXES,
+    },
+    error::{
+        SheetError,
+        SheetError::{Api, Conflict, NotCached, Offline, ReadOnly, RowNotFound},
+    },
     range::Range,
-    reader::SheetReader,
+    reader::{SharedRows, SheetReader},
 };
-use google_sheets_api::{client::GoogleSheetsReadOnlyClient, RowData};
+use google_sheets_api::{
+    cache::SpreadsheetCache,
+    change::Change,
+    client::GoogleSheetsReadOnlyClient,
+    value_range::{DateTimeRenderOption, ValueRange, ValueRenderOption},
+    RowData, Spreadsheet,
+};
+use serde_json::{json, Value};
+use std::{cell::OnceCell, sync::Arc};
 use typed_builder::TypedBuilder;
 
+/// How `DELETE` statement is applied to the sheet.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DeleteMode {
+    /// Deletes rows from the sheet. Rows below them are shifted up.
+    Delete,
+    /// Clears values of rows, leaving empty rows so that other ranges won't shift.
+    Clear,
+}
+
+/// Creates the client which a sheet is fetched with.
+pub type ClientFactory = Box<dyn Fn() -> Result<GoogleSheetsReadOnlyClient, SheetError> + Send>;
+
+/// Local state of a sheet, to be restored when a transaction is rolled back.
+#[derive(Clone)]
+pub struct Snapshot {
+    rows: Arc<Vec<Vec<CellValue>>>,
+    row_ids: Arc<Vec<usize>>,
+    removed_rows: Vec<usize>,
+    touched_cells: Vec<(usize, Option<usize>)>,
+}
+
 #[derive(TypedBuilder)]
 pub struct Sheet {
-    #[builder]
-    client: GoogleSheetsReadOnlyClient,
+    // the client is created when the sheet is fetched first, so that credentials aren't required
+    // to load a snapshot
+    create_client: ClientFactory,
+    #[builder(default)]
+    client: OnceCell<GoogleSheetsReadOnlyClient>,
+    // cache which the client keeps fetched sheets in, to be read in offline mode
+    #[builder(default)]
+    cache: SpreadsheetCache,
+    // serves the sheet from the cache, without accessing Google
+    #[builder(default = false)]
+    offline: bool,
+    // values converted from fetched rows, which are shared with readers, and copied only when
+    // they are changed while shared
+    #[builder(default)]
+    rows: Arc<Vec<Vec<CellValue>>>,
+    // rows as fetched from the sheet, to detect changes made by others before writing
+    #[builder(default)]
+    fetched_rows: Vec<RowData>,
+    // rowid and column index, or none for a whole row, of cells to be changed
+    #[builder(default)]
+    touched_cells: Vec<(usize, Option<usize>)>,
+    // row number in the sheet at the time of opening, for each row, which is used as rowid
+    #[builder(default)]
+    row_ids: Arc<Vec<usize>>,
+    // rowids of rows deleted from the sheet, to locate rows shifted by them
+    #[builder(default)]
+    removed_rows: Vec<usize>,
+    // numeric ID of the sheet, which is required to delete rows
     #[builder(default)]
-    rows: Vec<RowData>,
+    grid_id: i32,
     #[builder(setter(into))]
     id: String,
     #[builder(setter(into))]
     sheet: String,
     #[builder(setter(into))]
     range: Range,
+    #[builder(default = true)]
+    read_only: bool,
+    #[builder(default = DeleteMode::Delete)]
+    delete_mode: DeleteMode,
+    // fetches values only, rendered as specified, rather than grid data
+    #[builder(default)]
+    render: Option<ValueRenderOption>,
+    // number of rows to fetch at once, or none to fetch the whole range; only the first chunk is
+    // kept in `rows`, and readers fetch the rest as they advance
+    #[builder(default)]
+    chunk_size: Option<usize>,
+    // the chunk fetched last along with its first row number, which is reused by the next scan
+    // rather than fetched again, e.g. when the table is scanned for each row of another table
+    #[builder(default)]
+    last_chunk: Option<(usize, SharedRows)>,
+    #[builder(default)]
+    value_options: ValueOptions,
+    // whether metadata of cells follow values in each row, to be read as hidden columns
+    #[builder(default)]
+    metadata: bool,
+    #[builder(default)]
+    skip_empty_rows: bool,
 }
 
 impl Sheet {
     pub fn open(&mut self) -> Result<(), SheetError> {
-        match self.client.get(&self.id, &self.sheet, &self.range) {
-            Ok(sheet) => {
-                self.rows = sheet
-                    .sheets
-                    .unwrap()
-                    .get(0)
-                    .unwrap() // there only is a sheet
-                    .data
-                    .as_ref()
-                    .unwrap() // there should be a range, hence should have a row data
-                    .get(0)
-                    .unwrap()
-                    .row_data
-                    .as_ref()
-                    .unwrap()
-                    .to_vec();
-                Ok(())
-            }
+        if self.offline {
+            let (grid_id, rows) = self.load_cached()?;
+            return self.set_rows(grid_id, rows);
+        }
+
+        let range = match self.chunk_size {
+            Some(size) => self.get_chunk_range(self.range.r1, size),
+            None => self.range.clone(),
+        };
+        let (grid_id, rows) = self.fetch(&range)?;
+        self.set_rows(grid_id, rows)
+    }
+
+    // Loads the range from the cache. A chunked sheet has been cached chunk by chunk, hence its
+    // chunks are joined into the range unless the whole range has been cached.
+    fn load_cached(&self) -> Result<(i32, Vec<RowData>), SheetError> {
+        let load = |range: &Range| -> Result<(i32, Vec<RowData>), SheetError> {
+            self.cache
+                .load(&self.id, &self.sheet, range)?
+                .map(|entry| get_grid(entry.spreadsheet))
+                .ok_or_else(|| NotCached(format!("{}!{}", self.sheet, range)))
+        };
+
+        let size = match (load(&self.range), self.chunk_size) {
+            (Err(NotCached(_)), Some(size)) => size,
+            (result, _) => return result,
+        };
+        let mut grid_id = 0;
+        let mut rows = Vec::new();
+        for r1 in (self.range.r1..=self.range.r2).step_by(size) {
+            let (id, chunk) = load(&self.get_chunk_range(r1, size))?;
+            // empty rows at the end of a chunk are omitted, hence rows are padded up to the chunk
+            rows.resize(r1 - self.range.r1, RowData::default());
+            rows.extend(chunk);
+            grid_id = id;
+        }
+        Ok((grid_id, rows))
+    }
+
+    /// Whether the sheet is fetched in chunks, which is not the case in offline mode.
+    pub fn is_chunked(&self) -> bool {
+        self.chunk_size.is_some() && !self.offline
+    }
+
+    /// Fetches the chunk starting at row `r1` unless it's the one fetched last, and returns its
+    /// rows along with the row number of the next chunk, if any.
+    pub fn fetch_chunk(&mut self, r1: usize) -> Result<(SharedRows, Option<usize>), SheetError> {
+        let next_row = self.get_next_chunk_row(r1);
+        if let Some((_, rows)) = self.last_chunk.as_ref().filter(|(r, _)| *r == r1) {
+            return Ok((Arc::clone(rows), next_row));
+        }
+
+        let size = self.chunk_size.unwrap_or(self.range.r2 + 1 - self.range.r1);
+        let (_, rows) = self.fetch(&self.get_chunk_range(r1, size))?;
+        let rows = Arc::new(
+            rows.iter()
+                .enumerate()
+                .map(|(i, row)| self.to_values(r1 + i, row))
+                .collect::<Vec<_>>(),
+        );
+        self.last_chunk = Some((r1, Arc::clone(&rows)));
+        Ok((rows, next_row))
+    }
+
+    fn get_chunk_range(&self, r1: usize, size: usize) -> Range {
+        Range {
+            r1,
+            r2: (r1 + size - 1).min(self.range.r2),
+            ..self.range.clone()
+        }
+    }
+
+    // Empty rows are omitted from a chunk, hence the next chunk always follows the full chunk size.
+    // Fetching continues until the end of the range even after an empty chunk, as rows may follow
+    // blank ones.
+    fn get_next_chunk_row(&self, r1: usize) -> Option<usize> {
+        if !self.is_chunked() {
+            return None;
+        }
+
+        let next_row = r1 + self.chunk_size?;
+        (next_row <= self.range.r2).then_some(next_row)
+    }
+
+    // Returns grid ID of the sheet along with rows in the range.
+    fn fetch(&self, range: &Range) -> Result<(i32, Vec<RowData>), SheetError> {
+        if let Some(render) = self.render {
+            // dates can't be converted without their formats, hence either kept as serial numbers
+            // or displayed ones
+            let date_time_render = match self.value_options.date_time {
+                DateTimeMode::Serial => DateTimeRenderOption::SerialNumber,
+                _ => DateTimeRenderOption::FormattedString,
+            };
+            let values = self.client()?.get_values(
+                &self.id,
+                &self.sheet,
+                range,
+                render,
+                date_time_render,
+            )?;
+            // grid ID is only required to write back, which isn't allowed with values only
+            return Ok((0, get_row_data_from_values(&values)));
+        }
+
+        match self.client()?.get(&self.id, &self.sheet, range) {
+            Ok(spreadsheet) => Ok(get_grid(spreadsheet)),
             Err(why) => Err(Api(why)),
         }
     }
 
+    fn client(&self) -> Result<&GoogleSheetsReadOnlyClient, SheetError> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+        let client = (self.create_client)()?;
+        Ok(self.client.get_or_init(|| client))
+    }
+
+    /// Exports fetched rows, to be imported later instead of fetching them again.
+    pub fn export(&self) -> String {
+        json!({ "gridId": self.grid_id, "rows": self.fetched_rows }).to_string()
+    }
+
+    pub fn import(&mut self, data: &str) -> Result<(), SheetError> {
+        let mut value: Value = serde_json::from_str(data)?;
+        let grid_id = value["gridId"].as_i64().unwrap_or_default() as i32;
+        let rows = serde_json::from_value(value["rows"].take())?;
+
+        self.set_rows(grid_id, rows)
+    }
+
+    fn load(&mut self, spreadsheet: Spreadsheet) -> Result<(), SheetError> {
+        let (grid_id, rows) = get_grid(spreadsheet);
+        self.set_rows(grid_id, rows)
+    }
+
+    fn set_rows(&mut self, grid_id: i32, rows: Vec<RowData>) -> Result<(), SheetError> {
+        self.grid_id = grid_id;
+        self.rows = Arc::new(
+            rows.iter()
+                .enumerate()
+                .map(|(i, row)| self.to_values(self.range.r1 + i, row))
+                .collect(),
+        );
+        self.fetched_rows = rows;
+        self.row_ids = Arc::new((self.range.r1..self.range.r1 + self.rows.len()).collect());
+        self.removed_rows.clear();
+        self.touched_cells.clear();
+        Ok(())
+    }
+
+    // Tells the cell of a formula error, which is reported when the value is read.
+    fn locate_error(&self, value: CellValue, row_number: usize, column_number: usize) -> CellValue {
+        match value {
+            CellValue::Error(error, _, message) => {
+                let cell = number_to_column_name(column_number);
+                CellValue::Error(
+                    error,
+                    format!("{}!{}{}", self.sheet, cell, row_number),
+                    message,
+                )
+            }
+            value => value,
+        }
+    }
+
     pub fn get_reader(&mut self) -> SheetReader {
-        SheetReader::new(self.rows.clone())
+        SheetReader::new(Arc::clone(&self.rows), Arc::clone(&self.row_ids))
+            .with_next_row(self.get_next_chunk_row(self.range.r1))
+            .with_skip_empty_rows(self.skip_empty_rows)
+    }
+
+    // Converts cells of a row, followed by their metadata if needed. Since trailing empty cells are
+    // omitted from a row, rows are padded to the width of the range, so that every row has a value
+    // for every column, and metadata are always at the same positions.
+    fn to_values(&self, row_number: usize, row: &RowData) -> Vec<CellValue> {
+        let width = self.get_width();
+        let c1 = column_name_to_number(&self.range.c1);
+        let mut values = to_values(row, &self.value_options)
+            .into_iter()
+            .enumerate()
+            .map(|(j, value)| self.locate_error(value, row_number, c1 + j))
+            .collect::<Vec<_>>();
+        values.resize(width, CellValue::Empty);
+        if self.metadata {
+            let cells = row.values.as_deref().unwrap_or_default();
+            values.extend((0..width).flat_map(|i| parse_metadata(cells.get(i))));
+        }
+        values
+    }
+
+    // Cells outside the range are never fetched, hence no row is wider than the range.
+    fn get_width(&self) -> usize {
+        column_name_to_number(&self.range.c2) + 1 - column_name_to_number(&self.range.c1)
+    }
+
+    /// Returns a reader which reads a row for each non-empty cell, consisting of the row number,
+    /// the column number, the A1 notation, the value, the formatted value, the formula, and the
+    /// type of the value. Rowids are sequential numbers of cells.
+    pub fn get_cells_reader(&self) -> SheetReader {
+        let c1 = column_name_to_number(&self.range.c1);
+        let text = |s: Option<&String>| s.map_or(CellValue::Empty, |s| CellValue::Str(s.clone()));
+
+        let mut rows = Vec::new();
+        for (i, row) in self.fetched_rows.iter().enumerate() {
+            for (j, cell) in row.values.iter().flatten().enumerate() {
+                let formula = cell
+                    .user_entered_value
+                    .as_ref()
+                    .and_then(|v| v.formula_value.as_ref());
+                let value_type = get_value_type(cell);
+                if value_type.is_none() && formula.is_none() {
+                    continue;
+                }
+
+                let (row_number, column_number) = (self.range.r1 + i, c1 + j);
+                rows.push(vec![
+                    CellValue::Int(row_number as i64),
+                    CellValue::Int(column_number as i64),
+                    CellValue::Str(format!(
+                        "{}{}",
+                        number_to_column_name(column_number),
+                        row_number
+                    )),
+                    self.locate_error(
+                        parse_value(Some(cell), &self.value_options),
+                        row_number,
+                        column_number,
+                    ),
+                    text(cell.formatted_value.as_ref()),
+                    text(formula),
+                    value_type.map_or(CellValue::Empty, |t| CellValue::Str(t.to_string())),
+                ]);
+            }
+        }
+
+        let row_ids = (1..=rows.len()).collect();
+        SheetReader::new(Arc::new(rows), Arc::new(row_ids))
     }
 
+    /// Returns a column for each column of the range, regardless of which cells are filled.
     pub fn get_columns(&mut self) -> Vec<String> {
-        if let Some(row) = self.rows.get(0) {
-            if let Some(cell_data) = row.values.as_ref() {
-                if !cell_data.is_empty() {
-                    return (0..cell_data.len())
-                        .into_iter()
-                        .map(|n| number_to_column_name(n + column_name_to_number(&self.range.c1)))
-                        .collect();
+        (0..self.get_width())
+            .map(|n| number_to_column_name(n + column_name_to_number(&self.range.c1)))
+            .collect()
+    }
+
+    /// Returns columns of metadata, which follow the columns of values, e.g. `A_formula`.
+    pub fn get_hidden_columns(&mut self) -> Vec<String> {
+        if !self.metadata {
+            return Vec::new();
+        }
+
+        self.get_columns()
+            .iter()
+            .flat_map(|column| {
+                METADATA_SUFFIXES
+                    .iter()
+                    .map(move |suffix| format!("{}_{}", column, suffix))
+            })
+            .collect()
+    }
+
+    /// Applies values which differ from current ones to the row identified by `rowid`, and returns
+    /// changes to be written to the sheet.
+    pub fn update_row(
+        &mut self,
+        rowid: usize,
+        mut values: Vec<CellValue>,
+    ) -> Result<Vec<Change>, SheetError> {
+        self.check_writable()?;
+        // metadata are read-only
+        if self.metadata {
+            values.truncate(self.get_width());
+        }
+
+        let index = self.get_index(rowid)?;
+        let row_index = shifted_row_number(rowid, &self.removed_rows) - 1;
+        let c1 = column_name_to_number(&self.range.c1) - 1;
+        let cells = &mut Arc::make_mut(&mut self.rows)[index];
+
+        let mut changes = Vec::new();
+        for (i, value) in values.into_iter().enumerate() {
+            if cells.get(i).unwrap_or(&CellValue::Empty) == &value {
+                continue;
+            }
+
+            // converted according to the current cell, e.g. 1 and 0 are written as booleans to
+            // checkboxes, as they're read
+            let cell = self
+                .fetched_rows
+                .get(rowid - self.range.r1)
+                .and_then(|row| row.values.as_ref())
+                .and_then(|cells| cells.get(i));
+            let json = to_user_entered(&value, cell, &self.value_options);
+
+            changes.push(Change::UpdateCell {
+                row_index,
+                column_index: c1 + i,
+                value: json,
+            });
+            self.touched_cells.push((rowid, Some(i)));
+            if cells.len() <= i {
+                cells.resize(i + 1, CellValue::Empty);
+            }
+            cells[i] = value;
+        }
+        Ok(changes)
+    }
+
+    /// Deletes, or clears according to the delete mode, the row identified by `rowid`, and returns
+    /// a change to be written to the sheet.
+    pub fn delete_row(&mut self, rowid: usize) -> Result<Change, SheetError> {
+        self.check_writable()?;
+
+        let index = self.get_index(rowid)?;
+        let row_index = shifted_row_number(rowid, &self.removed_rows) - 1;
+
+        let change = match self.delete_mode {
+            DeleteMode::Delete => {
+                self.removed_rows.push(rowid);
+                Change::DeleteRow { row_index }
+            }
+            DeleteMode::Clear => Change::ClearRow {
+                row_index,
+                start_column_index: column_name_to_number(&self.range.c1) - 1,
+                end_column_index: column_name_to_number(&self.range.c2),
+            },
+        };
+
+        self.touched_cells.push((rowid, None));
+        Arc::make_mut(&mut self.rows).remove(index);
+        Arc::make_mut(&mut self.row_ids).remove(index);
+        Ok(change)
+    }
+
+    /// Writes changes to the sheet at once, unless cells to be changed have been changed by others
+    /// since they were fetched. The sheet is reloaded with the result, hence rowids become current
+    /// row numbers again.
+    pub fn commit(&mut self, changes: &[Change]) -> Result<(), SheetError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let current = self.client()?.get(&self.id, &self.sheet, &self.range)?;
+        if let Some(sheet) = current.sheets.as_ref().and_then(|sheets| sheets.first()) {
+            self.verify(&get_row_data(sheet))?;
+        }
+
+        let spreadsheet = self.client()?.batch_update(
+            &self.id,
+            &self.sheet,
+            &self.range,
+            self.grid_id,
+            changes,
+        )?;
+        self.load(spreadsheet)
+    }
+
+    // Fails if any of touched cells differs between fetched rows and current rows.
+    fn verify(&self, current_rows: &[RowData]) -> Result<(), SheetError> {
+        let c1 = column_name_to_number(&self.range.c1);
+        // cells are compared as fetched rather than converted, since options like `TRIM` or
+        // `ERRORS 'null'` would hide changes, e.g. of whitespaces, errors, or formats of dates
+        let cell = |rows: &[RowData], i: usize, j: usize| {
+            let cell = rows
+                .get(i)
+                .and_then(|row| row.values.as_ref())
+                .and_then(|cells| cells.get(j));
+            json!([
+                cell.and_then(|c| c.effective_value.as_ref()),
+                cell.and_then(|c| c.formatted_value.as_ref())
+            ])
+        };
+
+        for (rowid, column) in &self.touched_cells {
+            let i = rowid - self.range.r1;
+            let columns = match column {
+                Some(j) => *j..*j + 1,
+                None => 0..column_name_to_number(&self.range.c2) + 1 - c1,
+            };
+
+            for j in columns {
+                if cell(&self.fetched_rows, i, j) != cell(current_rows, i, j) {
+                    return Err(Conflict(format!(
+                        "{}!{}{}",
+                        self.sheet,
+                        number_to_column_name(c1 + j),
+                        rowid
+                    )));
                 }
             }
         }
 
-        Vec::new()
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            rows: Arc::clone(&self.rows),
+            row_ids: Arc::clone(&self.row_ids),
+            removed_rows: self.removed_rows.clone(),
+            touched_cells: self.touched_cells.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.rows = snapshot.rows;
+        self.row_ids = snapshot.row_ids;
+        self.removed_rows = snapshot.removed_rows;
+        self.touched_cells = snapshot.touched_cells;
+    }
+
+    fn check_writable(&self) -> Result<(), SheetError> {
+        if self.read_only {
+            return Err(ReadOnly);
+        }
+        if self.offline {
+            return Err(Offline);
+        }
+
+        Ok(())
+    }
+
+    fn get_index(&self, rowid: usize) -> Result<usize, SheetError> {
+        self.row_ids
+            .iter()
+            .position(|id| *id == rowid)
+            .ok_or(RowNotFound(rowid))
     }
 }
 
-fn column_name_to_number(name: impl Into<String>) -> usize {
+fn get_grid(spreadsheet: Spreadsheet) -> (i32, Vec<RowData>) {
+    let sheets = spreadsheet.sheets.unwrap();
+    let sheet = sheets.first().unwrap(); // there only is a sheet
+
+    (
+        sheet
+            .properties
+            .as_ref()
+            .and_then(|p| p.sheet_id)
+            .unwrap_or_default(),
+        get_row_data(sheet),
+    )
+}
+
+fn get_row_data(sheet: &google_sheets_api::Sheet) -> Vec<RowData> {
+    sheet
+        .data
+        .as_ref()
+        .unwrap() // there should be a range, hence should have a row data
+        .first()
+        .unwrap()
+        .row_data
+        .clone()
+        .unwrap_or_default() // no row data if the range is empty
+}
+
+// Converts cells once, rather than every time they are read.
+fn to_values(row: &RowData, options: &ValueOptions) -> Vec<CellValue> {
+    row.values
+        .iter()
+        .flatten()
+        .map(|cell| parse_value(Some(cell), options))
+        .collect()
+}
+
+fn get_row_data_from_values(values: &ValueRange) -> Vec<RowData> {
+    values
+        .values
+        .iter()
+        .map(|row| RowData {
+            values: Some(row.iter().map(cell_data_from_json).collect()),
+        })
+        .collect()
+}
+
+// Returns current row number in the sheet of the row identified by `rowid`, considering rows
+// deleted above it.
+fn shifted_row_number(rowid: usize, removed_rows: &[usize]) -> usize {
+    rowid - removed_rows.iter().filter(|r| **r < rowid).count()
+}
+
+pub fn column_name_to_number(name: impl Into<String>) -> usize {
     let mut num = 0;
 
     for c in name.into().chars() {
@@ -74,7 +599,7 @@ fn column_name_to_number(name: impl Into<String>) -> usize {
     num
 }
 
-fn number_to_column_name(num: usize) -> String {
+pub fn number_to_column_name(num: usize) -> String {
     let mut num = num;
     let mut column_name = String::from("");
 
@@ -91,9 +616,300 @@ fn number_to_column_name(num: usize) -> String {
     column_name.trim().to_string()
 }
 
+#[cfg(test)]
+impl Sheet {
+    /// Creates a writable sheet of the range, whose client has no valid credentials, for tests.
+    pub(crate) fn for_test(range: &str) -> Self {
+        Sheet::builder()
+            .create_client(Box::new(|| {
+                Ok(GoogleSheetsReadOnlyClient::builder()
+                    .client_id("id")
+                    .client_secret("secret")
+                    .build())
+            }))
+            .id("id")
+            .sheet("Sheet1")
+            .range(range)
+            .read_only(false)
+            .build()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::sheet::{column_name_to_number, number_to_column_name};
+    use crate::{
+        cell_value::{CellValue, ErrorMode},
+        error::SheetError::{Conflict, NoEnvironmentVariable},
+        sheet::{
+            column_name_to_number, number_to_column_name, shifted_row_number, DeleteMode, Sheet,
+        },
+    };
+    use google_sheets_api::{
+        cache::SpreadsheetCache, change::Change, CellData, ErrorValue, ExtendedValue, RowData,
+        Spreadsheet,
+    };
+    use serde_json::{json, Value};
+    use std::{env::temp_dir, sync::Arc};
+
+    fn row(values: &[i64]) -> RowData {
+        RowData {
+            values: Some(values.iter().map(|v| CellValue::Int(*v).into()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut sheet = Sheet::for_test("A2:B3");
+        sheet.fetched_rows = vec![row(&[1, 2]), row(&[3, 4])];
+        sheet.touched_cells = vec![(2, Some(1))];
+
+        assert!(sheet.verify(&[row(&[1, 2]), row(&[3, 4])]).is_ok());
+        assert!(sheet.verify(&[row(&[1, 2]), row(&[5, 4])]).is_ok());
+        assert!(matches!(
+            sheet.verify(&[row(&[1, 5]), row(&[3, 4])]),
+            Err(Conflict(cell)) if cell == "Sheet1!B2"
+        ));
+    }
+
+    #[test]
+    fn test_verify_fetched_cells() {
+        // changes which look the same once converted are still conflicts
+        let text = |s: &str| RowData {
+            values: Some(vec![CellValue::Str(s.into()).into()]),
+        };
+        let mut sheet = Sheet::for_test("A2:A2");
+        sheet.fetched_rows = vec![text("x")];
+        sheet.touched_cells = vec![(2, Some(0))];
+        sheet.value_options.trim = true;
+
+        assert!(sheet.verify(&[text("x")]).is_ok());
+        assert!(matches!(sheet.verify(&[text(" x")]), Err(Conflict(_))));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut sheet = Sheet::for_test("B2:C3");
+        sheet.value_options.errors = ErrorMode::Fail;
+        let error = CellData {
+            effective_value: Some(ExtendedValue {
+                error_value: Some(ErrorValue {
+                    type_: Some("DIVIDE_BY_ZERO".to_string()),
+                    message: Some("Function DIVIDE parameter 2 cannot be zero.".to_string()),
+                }),
+                ..Default::default()
+            }),
+            formatted_value: Some("#DIV/0!".to_string()),
+            ..Default::default()
+        };
+        let mut rows = vec![row(&[1, 2]), row(&[3, 4])];
+        rows[1].values.as_mut().unwrap()[1] = error;
+
+        // loaded without failing, as the error is only reported when the cell is read
+        assert!(sheet.set_rows(0, rows).is_ok());
+        assert_eq!(CellValue::Int(3), sheet.rows[1][0]);
+        assert!(matches!(
+            &sheet.rows[1][1],
+            CellValue::Error(error, cell, _) if error == "#DIV/0!" && cell == "Sheet1!C3"
+        ));
+    }
+
+    #[test]
+    fn test_get_cells_reader() {
+        let mut sheet = Sheet::for_test("B2:C3");
+        sheet.fetched_rows = vec![
+            row(&[1, 2]),
+            RowData {
+                values: Some(vec![CellData::default(), CellValue::Int(4).into()]),
+            },
+        ];
+        let mut reader = sheet.get_cells_reader();
+
+        assert_eq!(Some(&CellValue::Str("B2".into())), reader.get_value(2));
+        reader.move_next();
+        reader.move_next();
+        assert_eq!(3, reader.get_rowid());
+        assert_eq!(
+            vec![
+                CellValue::Int(3),
+                CellValue::Int(3),
+                CellValue::Str("C3".into()),
+                CellValue::Int(4),
+                CellValue::Str("4".into()),
+                CellValue::Empty,
+                CellValue::Str("number".into()),
+            ],
+            (0..7)
+                .map(|i| reader.get_value(i).unwrap().clone())
+                .collect::<Vec<_>>()
+        );
+        reader.move_next();
+        assert!(!reader.has_value());
+    }
+
+    #[test]
+    fn test_create_client_lazily() {
+        let mut sheet = Sheet::builder()
+            .create_client(Box::new(|| {
+                Err(NoEnvironmentVariable("LIBGSQLITE_GOOGLE_CLIENT_ID".into()))
+            }))
+            .id("id")
+            .sheet("Sheet1")
+            .range("A1:A1")
+            .build();
+
+        // a snapshot is loaded without credentials, which are only required to fetch the sheet
+        assert!(sheet.import(r#"{"gridId":0,"rows":[{}]}"#).is_ok());
+        assert!(matches!(sheet.open(), Err(NoEnvironmentVariable(_))));
+    }
+
+    #[test]
+    fn test_reversed_range() {
+        let mut sheet = Sheet::for_test("D2:B5");
+
+        assert_eq!(3, sheet.get_width());
+        assert_eq!(vec!["B", "C", "D"], sheet.get_columns());
+    }
+
+    #[test]
+    fn test_reversed_range_rows() {
+        // rowids are row numbers from the top of the range, so that rows written are in the range
+        let mut sheet = Sheet::for_test("D7:A2");
+        sheet.delete_mode = DeleteMode::Clear;
+        sheet.import(r#"{"gridId":0,"rows":[{},{}]}"#).unwrap();
+
+        assert_eq!(vec![2, 3], *sheet.row_ids);
+        assert!(sheet.delete_row(7).is_err());
+        assert_eq!(
+            Change::ClearRow {
+                row_index: 1,
+                start_column_index: 0,
+                end_column_index: 4
+            },
+            sheet.delete_row(2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ragged_rows() {
+        let mut sheet = Sheet::for_test("B2:D5");
+        sheet
+            .import(r#"{"gridId":0,"rows":[{"values":[{"formattedValue":"1","effectiveValue":{"numberValue":1}}]},{},{"values":[{},{},{"formattedValue":"3","effectiveValue":{"numberValue":3}}]}]}"#)
+            .unwrap();
+
+        assert_eq!(vec!["B", "C", "D"], sheet.get_columns());
+
+        let mut reader = sheet.get_reader();
+        let mut rows = Vec::new();
+        while reader.has_value() {
+            rows.push((
+                reader.get_rowid(),
+                (0..3)
+                    .map(|i| reader.get_value(i).cloned())
+                    .collect::<Vec<_>>(),
+            ));
+            reader.move_next();
+        }
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    vec![
+                        Some(CellValue::Int(1)),
+                        Some(CellValue::Empty),
+                        Some(CellValue::Empty)
+                    ]
+                ),
+                (3, vec![Some(CellValue::Empty); 3]),
+                (
+                    4,
+                    vec![
+                        Some(CellValue::Empty),
+                        Some(CellValue::Empty),
+                        Some(CellValue::Int(3))
+                    ]
+                ),
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut sheet = Sheet::for_test("B2:C11");
+        sheet.chunk_size = Some(4);
+
+        assert_eq!("B6:C9", sheet.get_chunk_range(6, 4).to_string());
+        assert_eq!("B10:C11", sheet.get_chunk_range(10, 4).to_string());
+        assert_eq!(Some(6), sheet.get_next_chunk_row(2));
+        // a chunk of blank rows doesn't stop fetching
+        assert_eq!(Some(10), sheet.get_next_chunk_row(6));
+        assert_eq!(None, sheet.get_next_chunk_row(10));
+    }
+
+    #[test]
+    fn test_load_cached_chunks() {
+        let mut sheet = Sheet::for_test("A2:A6");
+        sheet.cache = SpreadsheetCache::new(temp_dir().join("gsqlite-test-cached-chunks"));
+        sheet.chunk_size = Some(2);
+        sheet.offline = true;
+        let spreadsheet = |values: Value| {
+            serde_json::from_value::<Spreadsheet>(json!({
+                "sheets": [{ "data": [{ "rowData": values }] }]
+            }))
+            .unwrap()
+        };
+        let number = |n: i64| json!({ "values": [CellData::from(CellValue::Int(n))] });
+        let store = |range: &str, values: Value| {
+            sheet
+                .cache
+                .store("id", "Sheet1", range, &spreadsheet(values), None, "")
+                .unwrap()
+        };
+
+        // the second chunk is blank, and the last one is shorter
+        store("A2:A3", json!([number(1)]));
+        store("A4:A5", json!([]));
+        store("A6:A6", json!([number(5)]));
+        sheet.open().unwrap();
+
+        let mut reader = sheet.get_reader();
+        let mut rows = Vec::new();
+        while reader.has_value() {
+            rows.push((reader.get_rowid(), reader.get_value(0).cloned()));
+            reader.move_next();
+        }
+        assert_eq!(
+            vec![
+                (2, Some(CellValue::Int(1))),
+                (3, Some(CellValue::Empty)),
+                (4, Some(CellValue::Empty)),
+                (5, Some(CellValue::Empty)),
+                (6, Some(CellValue::Int(5))),
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    fn test_reuse_last_chunk() {
+        let rows = Arc::new(vec![vec![CellValue::Int(1)]]);
+        let mut sheet = Sheet::for_test("B2:C11");
+        sheet.chunk_size = Some(4);
+        sheet.last_chunk = Some((6, Arc::clone(&rows)));
+
+        // returned without fetching, which would fail without credentials
+        let (chunk, next_row) = sheet.fetch_chunk(6).unwrap();
+        assert!(Arc::ptr_eq(&rows, &chunk));
+        assert_eq!(Some(10), next_row);
+    }
+
+    #[test]
+    fn test_shifted_row_number() {
+        assert_eq!(5, shifted_row_number(5, &[]));
+        assert_eq!(5, shifted_row_number(5, &[6, 7]));
+        assert_eq!(4, shifted_row_number(5, &[3]));
+        assert_eq!(3, shifted_row_number(5, &[2, 3, 7]));
+    }
 
     #[test]
     fn test_number_to_column_name() {
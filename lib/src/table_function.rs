@@ -0,0 +1,196 @@
+use crate::{
+    error::{
+        SheetError,
+        SheetError::{InvalidRange, NoId, NoSheet},
+    },
+    range::Range,
+    sheet::{column_name_to_number, number_to_column_name},
+    sqlite3ext::SQLITE_INDEX_CONSTRAINT_EQ,
+};
+use std::{ffi::CString, os::raw::c_int};
+
+// Since the schema has to be declared before arguments are given, the table-valued function has
+// fixed columns, A to Z, followed by hidden columns for arguments.
+const COLUMNS: usize = 26;
+const ARGUMENTS: [&str; 3] = ["id", "sheet", "range"];
+const CELL_COLUMNS: [&str; 7] = ["row", "col", "a1", "value", "formatted", "formula", "type"];
+const CELL_ARGUMENTS: [&str; 3] = ["sheet_url", "sheet", "range"];
+
+/// Table-valued functions, both of which take the spreadsheet, the sheet, and the range.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TableFunction {
+    /// `gsqlite(id, sheet, range)`, which has a row for each row of the range.
+    Sheet,
+    /// `gsqlite_cells(sheet_url, sheet, range)`, which has a row for each cell of the range.
+    Cells,
+}
+
+impl TableFunction {
+    // Returns the number of columns other than arguments, which follow them.
+    fn get_column_count(self) -> usize {
+        match self {
+            TableFunction::Sheet => COLUMNS,
+            TableFunction::Cells => CELL_COLUMNS.len(),
+        }
+    }
+
+    pub fn create_declare_statement(self) -> CString {
+        let (columns, arguments) = match self {
+            TableFunction::Sheet => (
+                (1..=COLUMNS).map(number_to_column_name).collect(),
+                ARGUMENTS,
+            ),
+            TableFunction::Cells => (
+                CELL_COLUMNS
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>(),
+                CELL_ARGUMENTS,
+            ),
+        };
+        let columns = columns
+            .into_iter()
+            .chain(arguments.iter().map(|a| format!("{} HIDDEN", a)))
+            .collect::<Vec<_>>();
+
+        CString::new(format!("CREATE TABLE sheet({})", columns.join(", "))).unwrap()
+    }
+}
+
+/// Arguments of table-valued functions.
+pub struct Arguments {
+    pub id: String,
+    pub sheet: String,
+    pub range: Range,
+}
+
+pub enum FunctionColumn {
+    /// Index of a cell in a row of the range
+    Cell(usize),
+    /// Value of an argument
+    Argument(String),
+    /// A column outside the range
+    Outside,
+}
+
+impl Arguments {
+    pub fn new(id: &str, sheet: &str, range: &str) -> Result<Self, SheetError> {
+        let range = Range::from(range);
+        if id.is_empty() {
+            return Err(NoId);
+        }
+        if sheet.is_empty() {
+            return Err(NoSheet);
+        }
+        if range.r1 == 0 || range.r2 == 0 {
+            return Err(InvalidRange);
+        }
+
+        Ok(Arguments {
+            id: id.to_string(),
+            sheet: sheet.to_string(),
+            range,
+        })
+    }
+
+    pub fn get_column(&self, function: TableFunction, column: usize) -> FunctionColumn {
+        let count = function.get_column_count();
+        if column >= count {
+            return match column - count {
+                0 => FunctionColumn::Argument(self.id.clone()),
+                1 => FunctionColumn::Argument(self.sheet.clone()),
+                _ => FunctionColumn::Argument(self.range.to_string()),
+            };
+        }
+
+        // every column of cells is a value of a row
+        if function == TableFunction::Cells {
+            return FunctionColumn::Cell(column);
+        }
+
+        let c1 = column_name_to_number(&self.range.c1) - 1;
+        let c2 = column_name_to_number(&self.range.c2) - 1;
+        if c1 <= column && column <= c2 {
+            FunctionColumn::Cell(column - c1)
+        } else {
+            FunctionColumn::Outside
+        }
+    }
+}
+
+/// Finds constraints to receive arguments from, given `(column, op, usable)` of constraints.
+/// Returns indices of constraints for id, sheet, and range in order, or `None` if any of them is
+/// missing.
+pub fn find_argument_constraints(
+    function: TableFunction,
+    constraints: &[(c_int, u8, bool)],
+) -> Option<[usize; 3]> {
+    let mut found = [None; 3];
+
+    for (i, (column, op, usable)) in constraints.iter().enumerate() {
+        let argument = (*column as usize).checked_sub(function.get_column_count());
+        if let Some(argument @ 0..=2) = argument {
+            if *usable && *op as c_int == SQLITE_INDEX_CONSTRAINT_EQ {
+                found[argument] = Some(i);
+            }
+        }
+    }
+
+    Some([found[0]?, found[1]?, found[2]?])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sqlite3ext::SQLITE_INDEX_CONSTRAINT_EQ,
+        table_function::{find_argument_constraints, TableFunction},
+    };
+
+    #[test]
+    fn test_create_declare_statement() {
+        assert_eq!(
+            "CREATE TABLE sheet(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, id HIDDEN, sheet HIDDEN, range HIDDEN)",
+            TableFunction::Sheet.create_declare_statement().to_str().unwrap()
+        );
+        assert_eq!(
+            "CREATE TABLE sheet(row, col, a1, value, formatted, formula, type, sheet_url HIDDEN, sheet HIDDEN, range HIDDEN)",
+            TableFunction::Cells.create_declare_statement().to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_find_argument_constraints() {
+        let eq = SQLITE_INDEX_CONSTRAINT_EQ as u8;
+
+        assert_eq!(
+            Some([2, 0, 3]),
+            find_argument_constraints(
+                TableFunction::Sheet,
+                &[
+                    (27, eq, true),
+                    (3, eq, true),
+                    (26, eq, true),
+                    (28, eq, true)
+                ]
+            )
+        );
+        assert_eq!(
+            None,
+            find_argument_constraints(
+                TableFunction::Sheet,
+                &[(26, eq, true), (27, eq, true), (28, eq, false)]
+            )
+        );
+        assert_eq!(
+            None,
+            find_argument_constraints(TableFunction::Sheet, &[(26, eq, true), (27, eq, true)])
+        );
+        assert_eq!(
+            Some([0, 1, 2]),
+            find_argument_constraints(
+                TableFunction::Cells,
+                &[(7, eq, true), (8, eq, true), (9, eq, true)]
+            )
+        );
+    }
+}
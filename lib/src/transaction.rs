@@ -0,0 +1,108 @@
+use crate::{
+    error::SheetError,
+    sheet::{Sheet, Snapshot},
+};
+use google_sheets_api::change::Change;
+use std::os::raw::c_int;
+
+/// Changes made in a SQLite transaction, which are buffered until the transaction is committed.
+#[derive(Default)]
+pub struct Transaction {
+    changes: Vec<Change>,
+    // local state of the sheet at the beginning of the transaction
+    snapshot: Option<Snapshot>,
+    // savepoint level, number of changes, and local state of the sheet at the savepoint
+    savepoints: Vec<(c_int, usize, Snapshot)>,
+}
+
+impl Transaction {
+    pub fn begin(&mut self, sheet: &Sheet) {
+        self.changes.clear();
+        self.snapshot = Some(sheet.snapshot());
+        self.savepoints.clear();
+    }
+
+    pub fn push(&mut self, changes: impl IntoIterator<Item = Change>) {
+        self.changes.extend(changes);
+    }
+
+    /// Writes buffered changes to the sheet. Once succeeded, they can't be rolled back.
+    pub fn flush(&mut self, sheet: &mut Sheet) -> Result<(), SheetError> {
+        sheet.commit(&self.changes)?;
+
+        self.changes.clear();
+        self.snapshot = Some(sheet.snapshot());
+        self.savepoints.clear();
+        Ok(())
+    }
+
+    pub fn commit(&mut self) {
+        self.changes.clear();
+        self.snapshot = None;
+        self.savepoints.clear();
+    }
+
+    pub fn rollback(&mut self, sheet: &mut Sheet) {
+        if let Some(snapshot) = self.snapshot.take() {
+            sheet.restore(snapshot);
+        }
+        self.changes.clear();
+        self.savepoints.clear();
+    }
+
+    pub fn savepoint(&mut self, level: c_int, sheet: &Sheet) {
+        self.savepoints.retain(|(l, _, _)| *l < level);
+        self.savepoints
+            .push((level, self.changes.len(), sheet.snapshot()));
+    }
+
+    pub fn release(&mut self, level: c_int) {
+        self.savepoints.retain(|(l, _, _)| *l < level);
+    }
+
+    pub fn rollback_to(&mut self, level: c_int, sheet: &mut Sheet) {
+        if let Some(pos) = self.savepoints.iter().position(|(l, _, _)| *l == level) {
+            let (_, len, snapshot) = &self.savepoints[pos];
+            self.changes.truncate(*len);
+            sheet.restore(snapshot.clone());
+            self.savepoints.truncate(pos + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{sheet::Sheet, transaction::Transaction};
+
+    fn count_rows(sheet: &mut Sheet) -> usize {
+        let mut reader = sheet.get_reader();
+        let mut n = 0;
+        while reader.has_value() {
+            reader.move_next();
+            n += 1;
+        }
+        n
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut sheet = Sheet::for_test("A2:D4");
+        sheet.import(r#"{"gridId":0,"rows":[{},{},{}]}"#).unwrap();
+        let mut transaction = Transaction::default();
+
+        transaction.begin(&sheet);
+        transaction.push([sheet.delete_row(3).unwrap()]);
+        transaction.savepoint(0, &sheet);
+        transaction.push([sheet.delete_row(4).unwrap()]);
+        assert_eq!(2, transaction.changes.len());
+        assert_eq!(1, count_rows(&mut sheet));
+
+        transaction.rollback_to(0, &mut sheet);
+        assert_eq!(1, transaction.changes.len());
+        assert_eq!(2, count_rows(&mut sheet));
+
+        transaction.rollback(&mut sheet);
+        assert!(transaction.changes.is_empty());
+        assert_eq!(3, count_rows(&mut sheet));
+    }
+}